@@ -0,0 +1,634 @@
+use std::{fs, path::Path};
+
+use bevy::{input::InputSystem, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    calibration::typing_distance,
+    camera::SetViewEvent,
+    layout::{LayoutPath, TransformRecord},
+    LoadingState, HOVERED_BUTTON, NORMAL_BUTTON, PRESSED_BUTTON,
+};
+
+// How long the camera takes to reach a walkthrough key by default
+const DEFAULT_TRAVEL_SECONDS: f32 = 3.;
+// The views panel changes travel times by this much
+const TRAVEL_STEP: f32 = 0.5;
+// Samples per second in an exported camera path
+const EXPORT_RATE: f32 = 30.;
+
+const DIGIT_KEYS: [KeyCode; 9] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+
+// Saved camera views, which are stored in the layout. Ctrl+1 to Ctrl+9 save
+// the current view on that number and 1 to 9 jump back to it. Shift+1 to
+// Shift+9 add the view to the walkthrough, B plays the walkthrough or stops
+// it, Shift+B clears it and Ctrl+B exports the camera path as JSON. V opens a
+// panel that renames views and changes or removes walkthrough keys.
+pub struct BookmarksPlugin;
+
+impl Plugin for BookmarksPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Bookmarks>()
+            .init_resource::<Playback>()
+            .init_resource::<Renaming>()
+            .add_systems(Startup, spawn_views_panel)
+            // Runs before anything else reads the keys, so typing a name
+            // doesn't set off shortcuts or move the camera
+            .add_systems(PreUpdate, type_view_name.after(InputSystem))
+            .add_systems(
+                Update,
+                (
+                    (bookmark_keys, walkthrough_keys).run_if(not(typing_distance)),
+                    play_walkthrough,
+                    toggle_views_panel,
+                    views_buttons,
+                    show_views,
+                )
+                    .chain()
+                    .run_if(in_state(LoadingState::Loaded)),
+            );
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub name: String,
+    // The number key the view is saved on
+    pub key: Option<u8>,
+    pub transform: TransformRecord,
+}
+
+// A walkthrough key is a bookmark the camera travels to, by name so it
+// follows the bookmark if it is saved again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalkthroughKey {
+    pub bookmark: String,
+    pub seconds: f32,
+}
+
+#[derive(Resource, Default)]
+pub struct Bookmarks {
+    pub bookmarks: Vec<Bookmark>,
+    pub walkthrough: Vec<WalkthroughKey>,
+}
+
+impl Bookmarks {
+    fn on_key(&self, key: u8) -> Option<&Bookmark> {
+        self.bookmarks
+            .iter()
+            .find(|bookmark| bookmark.key == Some(key))
+    }
+
+    // The walkthrough's views and how long it takes to reach each, skipping
+    // keys whose bookmark no longer exists
+    fn keyframes(&self) -> Vec<(Transform, f32)> {
+        self.walkthrough
+            .iter()
+            .filter_map(|key| {
+                let bookmark = self
+                    .bookmarks
+                    .iter()
+                    .find(|bookmark| bookmark.name == key.bookmark)?;
+                Some((bookmark.transform.into(), key.seconds.max(0.)))
+            })
+            .collect()
+    }
+}
+
+// The camera at `time` seconds into a walkthrough. Positions follow a
+// Catmull-Rom curve through the keys and each leg eases in and out.
+fn sample(keyframes: &[(Transform, f32)], mut time: f32) -> Option<Transform> {
+    let (first, _) = keyframes.first()?;
+    if time <= 0. {
+        return Some(*first);
+    }
+    for index in 1..keyframes.len() {
+        let (to, seconds) = keyframes[index];
+        if time > seconds {
+            time -= seconds;
+            continue;
+        }
+        let from = keyframes[index - 1].0;
+        let before = keyframes[index.saturating_sub(2)].0.translation;
+        let after = keyframes
+            .get(index + 1)
+            .map_or(to.translation, |(after, _)| after.translation);
+        let t = if seconds > 0. { time / seconds } else { 1. };
+        let t = t * t * (3. - 2. * t);
+        return Some(Transform {
+            translation: catmull_rom(before, from.translation, to.translation, after, t),
+            rotation: from.rotation.slerp(to.rotation, t),
+            ..default()
+        });
+    }
+    keyframes.last().map(|(last, _)| *last)
+}
+
+fn catmull_rom(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, t: f32) -> Vec3 {
+    let (t2, t3) = (t * t, t * t * t);
+    0.5 * (2. * p1
+        + (p2 - p0) * t
+        + (2. * p0 - 5. * p1 + 4. * p2 - p3) * t2
+        + (3. * p1 - p0 - 3. * p2 + p3) * t3)
+}
+
+fn duration(keyframes: &[(Transform, f32)]) -> f32 {
+    keyframes.iter().skip(1).map(|(_, seconds)| seconds).sum()
+}
+
+#[derive(Resource, Default)]
+struct Playback {
+    // Seconds since the walkthrough started, while it plays
+    time: Option<f32>,
+}
+
+// The bookmark being renamed and the name typed so far
+#[derive(Resource, Default)]
+struct Renaming {
+    bookmark: Option<usize>,
+    name: String,
+}
+
+#[derive(Component)]
+struct ViewsPanel;
+
+// The part of the panel that is rebuilt when the views change
+#[derive(Component)]
+struct ViewsList;
+
+#[derive(Component, Clone, Copy)]
+enum ViewButton {
+    Rename(usize),
+    // Changes a walkthrough key's travel time by this many steps
+    Travel(usize, f32),
+    Remove(usize),
+}
+
+#[derive(Serialize)]
+struct CameraPathFrame {
+    time: f32,
+    translation: [f32; 3],
+    rotation: [f32; 4],
+}
+
+#[derive(Serialize)]
+struct CameraPathExport {
+    frame_rate: f32,
+    duration: f32,
+    keyframes: Vec<WalkthroughKey>,
+    frames: Vec<CameraPathFrame>,
+}
+
+fn digit_pressed(keys: &Input<KeyCode>) -> Option<u8> {
+    DIGIT_KEYS
+        .iter()
+        .position(|key| keys.just_pressed(*key))
+        .map(|index| index as u8 + 1)
+}
+
+fn bookmark_keys(
+    keys: Res<Input<KeyCode>>,
+    camera: Query<&Transform, With<Camera3d>>,
+    mut bookmarks: ResMut<Bookmarks>,
+    mut view_events: EventWriter<SetViewEvent>,
+    mut playback: ResMut<Playback>,
+) {
+    let Some(key) = digit_pressed(&keys) else {
+        return;
+    };
+    if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        return;
+    }
+    if keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        let Ok(transform) = camera.get_single() else {
+            return;
+        };
+        let transform = TransformRecord::from(transform);
+        let index = bookmarks
+            .bookmarks
+            .iter()
+            .position(|bookmark| bookmark.key == Some(key));
+        // Saving over a view keeps its name, so walkthroughs still find it
+        let name = match index {
+            Some(index) => {
+                bookmarks.bookmarks[index].transform = transform;
+                bookmarks.bookmarks[index].name.clone()
+            }
+            None => {
+                let name = format!("View {key}");
+                bookmarks.bookmarks.push(Bookmark {
+                    name: name.clone(),
+                    key: Some(key),
+                    transform,
+                });
+                name
+            }
+        };
+        info!("Saved the camera view as \"{name}\"");
+        return;
+    }
+    match bookmarks.on_key(key) {
+        Some(bookmark) => {
+            playback.time = None;
+            view_events.send(SetViewEvent(bookmark.transform.into()));
+        }
+        None => info!("No view saved on {key}, press Ctrl+{key} to save one"),
+    }
+}
+
+fn walkthrough_keys(
+    keys: Res<Input<KeyCode>>,
+    layout_path: Res<LayoutPath>,
+    mut bookmarks: ResMut<Bookmarks>,
+    mut playback: ResMut<Playback>,
+) {
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    if let (true, false, Some(key)) = (shift, ctrl, digit_pressed(&keys)) {
+        let Some(name) = bookmarks.on_key(key).map(|bookmark| bookmark.name.clone()) else {
+            info!("No view saved on {key}, press Ctrl+{key} to save one");
+            return;
+        };
+        info!("Added \"{name}\" to the walkthrough");
+        bookmarks.walkthrough.push(WalkthroughKey {
+            bookmark: name,
+            seconds: DEFAULT_TRAVEL_SECONDS,
+        });
+    }
+    if keys.just_pressed(KeyCode::B) && !ctrl {
+        if shift {
+            bookmarks.walkthrough.clear();
+            playback.time = None;
+            info!("Cleared the walkthrough");
+        } else if playback.time.take().is_none() {
+            if bookmarks.keyframes().len() < 2 {
+                warn!("A walkthrough needs at least two views, add them with Shift+1 to Shift+9");
+            } else {
+                playback.time = Some(0.);
+            }
+        }
+    }
+    if keys.just_pressed(KeyCode::B) && ctrl {
+        let path = layout_path.0.with_extension("walkthrough.json");
+        match export_camera_path(&bookmarks, &path) {
+            Ok(()) => info!("Exported the camera path to {}", path.display()),
+            Err(err) => error!(
+                "Unable to export the camera path to {}: {err}",
+                path.display()
+            ),
+        }
+    }
+}
+
+fn export_camera_path(bookmarks: &Bookmarks, path: &Path) -> Result<(), String> {
+    let keyframes = bookmarks.keyframes();
+    let duration = duration(&keyframes);
+    let count = (duration * EXPORT_RATE).ceil() as usize;
+    let export = CameraPathExport {
+        frame_rate: EXPORT_RATE,
+        duration,
+        keyframes: bookmarks.walkthrough.clone(),
+        frames: (0..=count)
+            .filter_map(|frame| {
+                let time = (frame as f32 / EXPORT_RATE).min(duration);
+                let transform = sample(&keyframes, time)?;
+                Some(CameraPathFrame {
+                    time,
+                    translation: transform.translation.to_array(),
+                    rotation: transform.rotation.to_array(),
+                })
+            })
+            .collect(),
+    };
+    let text = serde_json::to_string_pretty(&export).map_err(|err| err.to_string())?;
+    fs::write(path, text).map_err(|err| err.to_string())
+}
+
+fn play_walkthrough(
+    time: Res<Time>,
+    bookmarks: Res<Bookmarks>,
+    mut playback: ResMut<Playback>,
+    mut view_events: EventWriter<SetViewEvent>,
+) {
+    let Some(elapsed) = playback.time.as_mut() else {
+        return;
+    };
+    *elapsed += time.delta_seconds();
+    let keyframes = bookmarks.keyframes();
+    if let Some(transform) = sample(&keyframes, *elapsed) {
+        view_events.send(SetViewEvent(transform));
+    }
+    if *elapsed >= duration(&keyframes) {
+        playback.time = None;
+    }
+}
+
+fn toggle_views_panel(
+    keys: Res<Input<KeyCode>>,
+    mut panel: Query<&mut Visibility, With<ViewsPanel>>,
+    mut renaming: ResMut<Renaming>,
+) {
+    if !keys.just_pressed(KeyCode::V)
+        || keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight])
+    {
+        return;
+    }
+    let Ok(mut visibility) = panel.get_single_mut() else {
+        return;
+    };
+    *visibility = match *visibility {
+        Visibility::Hidden => Visibility::Inherited,
+        _ => Visibility::Hidden,
+    };
+    *renaming = Renaming::default();
+}
+
+// Enter renames the view, Escape cancels. Every key press is used up while
+// typing.
+fn type_view_name(
+    mut character_events: EventReader<ReceivedCharacter>,
+    mut keys: ResMut<Input<KeyCode>>,
+    mut renaming: ResMut<Renaming>,
+    mut bookmarks: ResMut<Bookmarks>,
+) {
+    let Some(index) = renaming.bookmark else {
+        character_events.clear();
+        return;
+    };
+    for event in character_events.read() {
+        if !event.char.is_control() {
+            renaming.name.push(event.char);
+        }
+    }
+    if keys.just_pressed(KeyCode::Back) {
+        renaming.name.pop();
+    }
+    if keys.just_pressed(KeyCode::Escape) {
+        *renaming = Renaming::default();
+    } else if keys.just_pressed(KeyCode::Return) {
+        let name = renaming.name.trim().to_string();
+        if name.is_empty() {
+            warn!("A view needs a name");
+        } else if bookmarks
+            .bookmarks
+            .iter()
+            .any(|bookmark| bookmark.name == name)
+        {
+            warn!("There is already a view called \"{name}\"");
+        } else if let Some(bookmark) = bookmarks.bookmarks.get_mut(index) {
+            let old = std::mem::replace(&mut bookmark.name, name.clone());
+            // Walkthrough keys find their view by name
+            for key in &mut bookmarks.walkthrough {
+                if key.bookmark == old {
+                    key.bookmark = name.clone();
+                }
+            }
+            info!("Renamed \"{old}\" to \"{name}\"");
+            *renaming = Renaming::default();
+        }
+    }
+    keys.reset_all();
+}
+
+fn views_buttons(
+    mut buttons: Query<(&Interaction, &mut BackgroundColor, &ViewButton), Changed<Interaction>>,
+    mut bookmarks: ResMut<Bookmarks>,
+    mut renaming: ResMut<Renaming>,
+    mut playback: ResMut<Playback>,
+) {
+    for (interaction, mut color, button) in &mut buttons {
+        match *interaction {
+            Interaction::Pressed => {
+                *color = PRESSED_BUTTON.into();
+                match *button {
+                    ViewButton::Rename(index) => {
+                        renaming.bookmark = Some(index);
+                        renaming.name.clear();
+                    }
+                    ViewButton::Travel(index, steps) => {
+                        if let Some(key) = bookmarks.walkthrough.get_mut(index) {
+                            key.seconds = (key.seconds + steps * TRAVEL_STEP).max(TRAVEL_STEP);
+                        }
+                    }
+                    ViewButton::Remove(index) => {
+                        if index < bookmarks.walkthrough.len() {
+                            let key = bookmarks.walkthrough.remove(index);
+                            playback.time = None;
+                            info!("Removed \"{}\" from the walkthrough", key.bookmark);
+                        }
+                    }
+                }
+            }
+            Interaction::Hovered => *color = HOVERED_BUTTON.into(),
+            Interaction::None => *color = NORMAL_BUTTON.into(),
+        }
+    }
+}
+
+fn text(value: &str) -> TextBundle {
+    TextBundle::from_section(
+        value,
+        TextStyle {
+            font_size: 18.,
+            ..default()
+        },
+    )
+}
+
+fn heading(value: &str) -> TextBundle {
+    TextBundle::from_section(
+        value,
+        TextStyle {
+            font_size: 25.,
+            ..default()
+        },
+    )
+}
+
+fn view_button(parent: &mut ChildBuilder, label: &str, button: ViewButton) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    min_width: Val::Px(28.),
+                    padding: UiRect::horizontal(Val::Px(6.)),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: NORMAL_BUTTON.into(),
+                ..default()
+            },
+            button,
+        ))
+        .with_children(|parent| {
+            parent.spawn(text(label));
+        });
+}
+
+// A name on the left and controls on the right
+fn view_row(parent: &mut ChildBuilder, name: &str, controls: impl FnOnce(&mut ChildBuilder)) {
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                justify_content: JustifyContent::SpaceBetween,
+                align_items: AlignItems::Center,
+                column_gap: Val::Px(8.),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn(text(name));
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        align_items: AlignItems::Center,
+                        column_gap: Val::Px(4.),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(controls);
+        });
+}
+
+// Next to the settings panel
+fn spawn_views_panel(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(340.),
+                    top: Val::Px(10.),
+                    width: Val::Px(360.),
+                    flex_direction: FlexDirection::Column,
+                    padding: UiRect::all(Val::Px(10.)),
+                    ..default()
+                },
+                background_color: Color::rgba(0., 0., 0., 0.8).into(),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            ViewsPanel,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        row_gap: Val::Px(4.),
+                        ..default()
+                    },
+                    ..default()
+                },
+                ViewsList,
+            ));
+        });
+}
+
+fn show_views(
+    mut commands: Commands,
+    bookmarks: Res<Bookmarks>,
+    renaming: Res<Renaming>,
+    list: Query<Entity, With<ViewsList>>,
+) {
+    if !bookmarks.is_changed() && !renaming.is_changed() {
+        return;
+    }
+    let Ok(list) = list.get_single() else {
+        return;
+    };
+    commands
+        .entity(list)
+        .despawn_descendants()
+        .with_children(|parent| {
+            parent.spawn(heading("Views"));
+            if bookmarks.bookmarks.is_empty() {
+                parent.spawn(text("Ctrl+1 to Ctrl+9 save the current view"));
+            }
+            for (index, bookmark) in bookmarks.bookmarks.iter().enumerate() {
+                let key = bookmark.key.map_or(String::new(), |key| format!("{key}: "));
+                let name = if renaming.bookmark == Some(index) {
+                    format!("{key}{}_", renaming.name)
+                } else {
+                    format!("{key}{}", bookmark.name)
+                };
+                view_row(parent, &name, |parent| {
+                    view_button(parent, "Rename", ViewButton::Rename(index));
+                });
+            }
+            if renaming.bookmark.is_some() {
+                parent.spawn(text("Type a name (Enter to confirm, Escape to cancel)"));
+            }
+            parent.spawn(heading("Walkthrough"));
+            if bookmarks.walkthrough.is_empty() {
+                parent.spawn(text("Shift+1 to Shift+9 add views"));
+            }
+            for (index, key) in bookmarks.walkthrough.iter().enumerate() {
+                view_row(parent, &key.bookmark, |parent| {
+                    // The walkthrough starts on the first key
+                    if index > 0 {
+                        view_button(parent, "-", ViewButton::Travel(index, -1.));
+                        parent.spawn(text(&format!("{:.1} s", key.seconds)));
+                        view_button(parent, "+", ViewButton::Travel(index, 1.));
+                    }
+                    view_button(parent, "Remove", ViewButton::Remove(index));
+                });
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(x: f32, seconds: f32) -> (Transform, f32) {
+        (Transform::from_xyz(x, 1., 0.), seconds)
+    }
+
+    #[test]
+    fn catmull_rom_passes_through_the_middle_points() {
+        let points = [Vec3::ZERO, Vec3::X, Vec3::new(2., 1., 0.), Vec3::splat(3.)];
+        assert!(catmull_rom(points[0], points[1], points[2], points[3], 0.)
+            .abs_diff_eq(points[1], 1e-6));
+        assert!(catmull_rom(points[0], points[1], points[2], points[3], 1.)
+            .abs_diff_eq(points[2], 1e-6));
+    }
+
+    #[test]
+    fn catmull_rom_follows_a_straight_line() {
+        let point = catmull_rom(Vec3::ZERO, Vec3::X, Vec3::X * 2., Vec3::X * 3., 0.5);
+        assert!(point.abs_diff_eq(Vec3::X * 1.5, 1e-6));
+    }
+
+    #[test]
+    fn samples_walkthrough_keys() {
+        // The first key's travel time is never used
+        let keyframes = [key(0., 9.), key(1., 2.), key(2., 4.), key(3., 1.)];
+        assert_eq!(duration(&keyframes), 7.);
+        let at = |time| sample(&keyframes, time).unwrap().translation.x;
+        assert_eq!(at(-1.), 0.);
+        assert!((at(2.) - 1.).abs() < 1e-6);
+        assert!((at(6.) - 2.).abs() < 1e-6);
+        assert_eq!(at(10.), 3.);
+        // Eased halfway through the second leg
+        assert!((at(4.) - 1.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn samples_nothing_without_keys() {
+        assert!(sample(&[], 1.).is_none());
+        assert_eq!(sample(&[key(3., 1.)], 1.).unwrap().translation.x, 3.);
+    }
+}
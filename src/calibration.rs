@@ -0,0 +1,283 @@
+use bevy::prelude::*;
+use bevy_mod_picking::prelude::*;
+
+use crate::{
+    measure::Units,
+    metadata::Metadata,
+    pivot::{ModelPivot, PivotPending},
+    placed_model_root,
+    selection::Selection,
+    LoadingState, PlacedModel,
+};
+
+// Sets a model's base scale from a known measurement. C with one model
+// selected starts calibrating it: click two points on the model, type the real
+// distance between them and press Enter. Escape cancels.
+pub struct CalibrationPlugin;
+
+impl Plugin for CalibrationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Calibration>()
+            .add_systems(Startup, spawn_calibration_panel)
+            .add_systems(
+                Update,
+                (
+                    start_calibration,
+                    pick_points,
+                    type_distance,
+                    draw_calibration,
+                    update_calibration_panel,
+                )
+                    .chain()
+                    .run_if(in_state(LoadingState::Loaded)),
+            );
+    }
+}
+
+// Points are kept in the frame of the model's scene, so they stay on the
+// model if it moves and the distance between them is in the file's units
+#[derive(Resource, Default)]
+pub struct Calibration {
+    model: Option<Entity>,
+    points: Vec<Vec3>,
+    distance: String,
+}
+
+impl Calibration {
+    fn cancel(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[derive(Component)]
+struct CalibrationPanel;
+
+// Run condition for shortcuts that would clash with typing the distance
+pub fn typing_distance(calibration: Res<Calibration>) -> bool {
+    calibration.points.len() == 2
+}
+
+// The scene entity of a placed model
+fn model_pivot(
+    model: Entity,
+    children: &Query<&Children>,
+    pivots: &Query<(Entity, &ModelPivot)>,
+) -> Option<Entity> {
+    children
+        .get(model)
+        .ok()?
+        .iter()
+        .copied()
+        .find(|child| pivots.contains(*child))
+}
+
+fn start_calibration(
+    keys: Res<Input<KeyCode>>,
+    selection: Res<Selection>,
+    mut calibration: ResMut<Calibration>,
+) {
+    if let Some(model) = calibration.model {
+        if keys.just_pressed(KeyCode::Escape) || !selection.contains(model) {
+            calibration.cancel();
+        }
+        return;
+    }
+    if !keys.just_pressed(KeyCode::C)
+        || keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight])
+    {
+        return;
+    }
+    match selection.models() {
+        [model] => {
+            calibration.model = Some(*model);
+            info!("Calibrating: click two points on the model");
+        }
+        _ => warn!("Select a single model to calibrate"),
+    }
+}
+
+fn pick_points(
+    mut click_events: EventReader<Pointer<Click>>,
+    mut calibration: ResMut<Calibration>,
+    parents: Query<&Parent>,
+    placed_models: Query<(), With<PlacedModel>>,
+    children: Query<&Children>,
+    pivots: Query<(Entity, &ModelPivot)>,
+    transforms: Query<&GlobalTransform>,
+) {
+    let Some(model) = calibration.model else {
+        click_events.clear();
+        return;
+    };
+    for event in click_events.read() {
+        if event.button != PointerButton::Primary || calibration.points.len() >= 2 {
+            continue;
+        }
+        let (Some(position), Some(pivot)) =
+            (event.hit.position, model_pivot(model, &children, &pivots))
+        else {
+            continue;
+        };
+        if placed_model_root(event.target, &parents, &placed_models) != Some(model) {
+            continue;
+        }
+        let Ok(pivot_transform) = transforms.get(pivot) else {
+            continue;
+        };
+        let point = pivot_transform
+            .affine()
+            .inverse()
+            .transform_point3(position);
+        calibration.points.push(point);
+        if calibration.points.len() == 2 {
+            info!("Type the distance between the points and press Enter");
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn type_distance(
+    mut commands: Commands,
+    mut character_events: EventReader<ReceivedCharacter>,
+    keys: Res<Input<KeyCode>>,
+    mut calibration: ResMut<Calibration>,
+    children: Query<&Children>,
+    pivots: Query<(Entity, &ModelPivot)>,
+    units: Res<Units>,
+    mut metadata: Metadata,
+) {
+    if calibration.points.len() < 2 {
+        character_events.clear();
+        return;
+    }
+    for event in character_events.read() {
+        if event.char.is_ascii_digit() || event.char == '.' {
+            calibration.distance.push(event.char);
+        }
+    }
+    if keys.just_pressed(KeyCode::Back) {
+        calibration.distance.pop();
+    }
+    if !keys.just_pressed(KeyCode::Return) {
+        return;
+    }
+    let Ok(distance) = calibration.distance.parse::<f32>() else {
+        warn!("{:?} is not a distance", calibration.distance);
+        return;
+    };
+    let distance = units.to_metres(distance);
+    let measured = calibration.points[0].distance(calibration.points[1]);
+    if distance <= 0. || measured <= f32::EPSILON {
+        warn!("Unable to calibrate with a distance of zero");
+        return;
+    }
+    let Some(model) = calibration
+        .model
+        .and_then(|model| model_pivot(model, &children, &pivots))
+        .and_then(|pivot| pivots.get(pivot).ok())
+        .map(|(_, pivot)| pivot.0.clone())
+    else {
+        calibration.cancel();
+        return;
+    };
+    let mut model_metadata = metadata.get(&model);
+    model_metadata.base_scale = distance / measured;
+    metadata.set(&model, model_metadata);
+    info!("Base scale set to {}", model_metadata.base_scale);
+    // Every copy of the model picks up the new scale
+    for (entity, pivot) in &pivots {
+        if pivot.0 == model {
+            commands.entity(entity).insert(PivotPending);
+        }
+    }
+    calibration.cancel();
+}
+
+fn draw_calibration(
+    calibration: Res<Calibration>,
+    children: Query<&Children>,
+    pivots: Query<(Entity, &ModelPivot)>,
+    transforms: Query<&GlobalTransform>,
+    mut gizmos: Gizmos,
+) {
+    let Some(pivot) = calibration
+        .model
+        .and_then(|model| model_pivot(model, &children, &pivots))
+        .and_then(|pivot| transforms.get(pivot).ok())
+    else {
+        return;
+    };
+    let points: Vec<Vec3> = calibration
+        .points
+        .iter()
+        .map(|point| pivot.transform_point(*point))
+        .collect();
+    for point in &points {
+        gizmos.sphere(*point, Quat::IDENTITY, 0.02, Color::YELLOW);
+    }
+    if let [from, to] = points[..] {
+        gizmos.line(from, to, Color::YELLOW);
+    }
+}
+
+fn spawn_calibration_panel(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(10.),
+                    bottom: Val::Px(10.),
+                    padding: UiRect::all(Val::Px(8.)),
+                    ..default()
+                },
+                background_color: Color::rgba(0.1, 0.1, 0.1, 0.9).into(),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            Pickable::IGNORE,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 16.,
+                        ..default()
+                    },
+                ),
+                CalibrationPanel,
+            ));
+        });
+}
+
+fn update_calibration_panel(
+    calibration: Res<Calibration>,
+    units: Res<Units>,
+    mut text: Query<(&mut Text, &Parent), With<CalibrationPanel>>,
+    mut panels: Query<&mut Visibility, With<Node>>,
+) {
+    if !calibration.is_changed() && !units.is_changed() {
+        return;
+    }
+    let Ok((mut text, parent)) = text.get_single_mut() else {
+        return;
+    };
+    let Ok(mut visibility) = panels.get_mut(parent.get()) else {
+        return;
+    };
+    if calibration.model.is_none() {
+        *visibility = Visibility::Hidden;
+        return;
+    }
+    *visibility = Visibility::Inherited;
+    text.sections[0].value = match calibration.points.len() {
+        0 => "Calibrate: click the first point".to_string(),
+        1 => "Calibrate: click the second point".to_string(),
+        _ => format!(
+            "Real distance in {}: {} (Enter to confirm, Escape to cancel)",
+            units.input_name(),
+            calibration.distance
+        ),
+    };
+}
@@ -0,0 +1,494 @@
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::{
+    input::mouse::{MouseScrollUnit, MouseWheel},
+    prelude::*,
+    render::{camera::ScalingMode, primitives::Aabb},
+    window::{CursorGrabMode, PrimaryWindow},
+};
+use bevy_flycam::FlyCam;
+
+use crate::{
+    model_bounds, room::Room, selection::Selection, world_bounds, LoadingState, PlacedModel,
+    RIGHT_SIDEBAR_WIDTH,
+};
+
+// How far above the top of the walls the plan camera sits
+const PLAN_HEIGHT: f32 = 10.;
+const MIN_ZOOM: f32 = 0.05;
+const MAX_ZOOM: f32 = 5.;
+// Pan speed with the movement keys, in screen heights per second
+const PAN_SPEED: f32 = 0.8;
+const FOOTPRINT_COLOR: Color = Color::rgb(0.2, 0.2, 0.2);
+const SELECTED_FOOTPRINT_COLOR: Color = Color::rgb(1., 0.6, 0.1);
+// Radians per pixel dragged
+const ORBIT_SPEED: f32 = 0.005;
+const MIN_ORBIT_DISTANCE: f32 = 0.2;
+const MAX_ORBIT_DISTANCE: f32 = 100.;
+// Framed bounds are padded so they don't touch the edges of the screen
+const FRAME_MARGIN: f32 = 1.2;
+
+// P switches between the current view and a top-down orthographic plan of the
+// room. In the plan the wheel zooms, the right or middle button drags the view
+// and WASD pans, and the outline of the room and every model's footprint are
+// drawn over the scene. O switches between the flycam and an orbit camera,
+// which circles a focus point with the right button, pans with the middle one
+// and zooms with the wheel. G switches to walking around the room, see
+// `walk`. F frames the selection and Shift+F the room.
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraMode>()
+            .init_resource::<OrbitCamera>()
+            .add_event::<SetViewEvent>()
+            .add_systems(
+                Update,
+                (
+                    switch_camera_mode,
+                    set_view,
+                    frame_view,
+                    (plan_controls, draw_plan).run_if(resource_equals(CameraMode::Plan)),
+                    orbit_controls.run_if(resource_equals(CameraMode::Orbit)),
+                )
+                    .chain()
+                    .run_if(in_state(LoadingState::Loaded)),
+            );
+    }
+}
+
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CameraMode {
+    #[default]
+    Fly,
+    Orbit,
+    Plan,
+    Walk,
+}
+
+impl CameraMode {
+    // Dragging with the middle button moves the camera, not models
+    pub fn pans_with_middle(self) -> bool {
+        matches!(self, CameraMode::Orbit | CameraMode::Plan)
+    }
+}
+
+// Moves the camera to a saved view. The plan and walking are left for the
+// flycam, and the orbit camera orbits whatever the view looks at.
+#[derive(Event)]
+pub struct SetViewEvent(pub Transform);
+
+// The orbit camera sits `distance` from `focus`, turned by `yaw` and `pitch`
+#[derive(Resource, Default)]
+struct OrbitCamera {
+    focus: Vec3,
+    distance: f32,
+    yaw: f32,
+    pitch: f32,
+}
+
+impl OrbitCamera {
+    // Orbits the point the camera is looking at, so the view doesn't change
+    fn from_view(transform: &Transform, room: &Room) -> Self {
+        let forward = transform.forward();
+        let floor = Ray {
+            origin: transform.translation,
+            direction: forward,
+        }
+        .intersect_plane(Vec3::ZERO, Vec3::Y);
+        let distance = floor
+            .filter(|distance| *distance < MAX_ORBIT_DISTANCE)
+            .unwrap_or_else(|| transform.translation.distance(room_center(room)))
+            .max(MIN_ORBIT_DISTANCE);
+        let (yaw, pitch, _) = transform.rotation.to_euler(EulerRot::YXZ);
+        Self {
+            focus: transform.translation + forward * distance,
+            distance,
+            yaw,
+            pitch,
+        }
+    }
+
+    fn transform(&self) -> Transform {
+        let rotation = Quat::from_euler(EulerRot::YXZ, self.yaw, self.pitch, 0.);
+        Transform {
+            translation: self.focus + rotation * Vec3::Z * self.distance,
+            rotation,
+            ..default()
+        }
+    }
+}
+
+// How far the cursor has moved since the last frame while the buttons are
+// held. Drags have to start outside the sidebar.
+#[derive(Default)]
+struct CursorDrag(Option<Vec2>);
+
+impl CursorDrag {
+    fn delta(&mut self, held: bool, window: &Window) -> Vec2 {
+        match (held, self.0, window.cursor_position()) {
+            (true, Some(last), Some(current)) => {
+                self.0 = Some(current);
+                current - last
+            }
+            (true, None, current) => {
+                self.0 = current.filter(|cursor| !over_sidebar(window, *cursor));
+                Vec2::ZERO
+            }
+            _ => {
+                self.0 = None;
+                Vec2::ZERO
+            }
+        }
+    }
+}
+
+fn over_sidebar(window: &Window, cursor: Vec2) -> bool {
+    cursor.x >= window.width() - RIGHT_SIDEBAR_WIDTH
+}
+
+// Steps the wheel was turned while the cursor was over the scene
+fn wheel_steps(wheel_events: &mut EventReader<MouseWheel>, window: &Window) -> f32 {
+    let in_scene = window
+        .cursor_position()
+        .is_some_and(|cursor| !over_sidebar(window, cursor));
+    wheel_events
+        .read()
+        .filter(|_| in_scene)
+        .map(|event| match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / 20.,
+        })
+        .sum()
+}
+
+// How big something at `distance` from the camera would have to be to look
+// the same size in the flycam, so on-screen handles keep their size in the
+// plan too
+pub fn view_scale(projection: &Projection, distance: f32) -> f32 {
+    match projection {
+        Projection::Perspective(_) => distance,
+        Projection::Orthographic(orthographic) => {
+            let fov = PerspectiveProjection::default().fov;
+            orthographic.area.height() / (2. * (fov / 2.).tan())
+        }
+    }
+}
+
+fn room_bounds(room: &Room) -> (Vec3, Vec3) {
+    let corners = room.corners();
+    let min = corners.iter().copied().fold(Vec2::INFINITY, Vec2::min);
+    let max = corners.iter().copied().fold(Vec2::NEG_INFINITY, Vec2::max);
+    (
+        Vec3::new(min.x, 0., min.y),
+        Vec3::new(max.x, room.wall_height, max.y),
+    )
+}
+
+pub fn room_center(room: &Room) -> Vec3 {
+    let (min, max) = room_bounds(room);
+    (min + max) / 2.
+}
+
+fn plan_view(room: &Room) -> (Transform, Projection) {
+    let (min, max) = room_bounds(room);
+    let center = (min + max) / 2.;
+    // Screen up is -Z
+    let transform = Transform::from_xyz(center.x, room.wall_height + PLAN_HEIGHT, center.z)
+        .looking_at(Vec3::new(center.x, 0., center.z), Vec3::NEG_Z);
+    let projection = Projection::Orthographic(OrthographicProjection {
+        scaling_mode: ScalingMode::FixedVertical((max - min).xz().max_element() * FRAME_MARGIN),
+        far: room.wall_height + PLAN_HEIGHT * 2.,
+        ..default()
+    });
+    (transform, projection)
+}
+
+// The view the plan was entered from is kept and restored when leaving it
+#[allow(clippy::too_many_arguments)]
+fn switch_camera_mode(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    room: Res<Room>,
+    mut mode: ResMut<CameraMode>,
+    mut orbit: ResMut<OrbitCamera>,
+    mut camera: Query<(Entity, &mut Transform, &mut Projection), With<Camera3d>>,
+    mut window: Query<&mut Window, With<PrimaryWindow>>,
+    mut before_plan: Local<Option<(CameraMode, Transform)>>,
+) {
+    let ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    let new_mode = if keys.just_pressed(KeyCode::P) {
+        match *mode {
+            CameraMode::Plan => before_plan.map_or(CameraMode::Fly, |(mode, _)| mode),
+            _ => CameraMode::Plan,
+        }
+    } else if keys.just_pressed(KeyCode::O) && !ctrl {
+        match *mode {
+            CameraMode::Fly | CameraMode::Walk => CameraMode::Orbit,
+            CameraMode::Orbit => CameraMode::Fly,
+            CameraMode::Plan => return,
+        }
+    } else if keys.just_pressed(KeyCode::G) {
+        match *mode {
+            CameraMode::Fly | CameraMode::Orbit => CameraMode::Walk,
+            CameraMode::Walk => CameraMode::Fly,
+            CameraMode::Plan => return,
+        }
+    } else {
+        return;
+    };
+    let Ok((entity, mut transform, mut projection)) = camera.get_single_mut() else {
+        return;
+    };
+    match (*mode, new_mode) {
+        (_, CameraMode::Plan) => {
+            *before_plan = Some((*mode, *transform));
+            (*transform, *projection) = plan_view(&room);
+        }
+        (CameraMode::Plan, _) => {
+            if let Some((_, view)) = before_plan.take() {
+                *transform = view;
+            }
+            *projection = Projection::Perspective(default());
+        }
+        (_, CameraMode::Orbit) => *orbit = OrbitCamera::from_view(&transform, &room),
+        // Walking starts from where the camera is if that is in the room
+        (_, CameraMode::Walk) if !room.contains(transform.translation.xz()) => {
+            let center = room_center(&room);
+            transform.translation.x = center.x;
+            transform.translation.z = center.z;
+        }
+        _ => {}
+    }
+    // The flycam only moves the camera while it has the cursor, and walking
+    // looks around the same way
+    if new_mode == CameraMode::Fly {
+        commands.entity(entity).insert(FlyCam);
+    } else {
+        commands.entity(entity).remove::<FlyCam>();
+    }
+    if let (true, Ok(mut window)) = (new_mode != CameraMode::Fly, window.get_single_mut()) {
+        let walking = new_mode == CameraMode::Walk;
+        window.cursor.grab_mode = if walking {
+            CursorGrabMode::Confined
+        } else {
+            CursorGrabMode::None
+        };
+        window.cursor.visible = !walking;
+    }
+    *mode = new_mode;
+    info!("{:?} view", *mode);
+}
+
+fn set_view(
+    mut commands: Commands,
+    mut view_events: EventReader<SetViewEvent>,
+    room: Res<Room>,
+    mut mode: ResMut<CameraMode>,
+    mut orbit: ResMut<OrbitCamera>,
+    mut camera: Query<(Entity, &mut Transform, &mut Projection), With<Camera3d>>,
+) {
+    let (Some(SetViewEvent(view)), Ok((entity, mut transform, mut projection))) =
+        (view_events.read().last(), camera.get_single_mut())
+    else {
+        return;
+    };
+    *transform = *view;
+    match *mode {
+        CameraMode::Fly => {}
+        CameraMode::Orbit => *orbit = OrbitCamera::from_view(view, &room),
+        CameraMode::Plan | CameraMode::Walk => {
+            *projection = Projection::Perspective(default());
+            commands.entity(entity).insert(FlyCam);
+            *mode = CameraMode::Fly;
+        }
+    }
+}
+
+// Fits a box into the view, keeping the direction the camera looks in
+fn frame(
+    bounds: (Vec3, Vec3),
+    orbit: &mut OrbitCamera,
+    transform: &mut Transform,
+    projection: &mut Projection,
+) {
+    let (min, max) = bounds;
+    let center = (min + max) / 2.;
+    let radius = ((max - min).length() / 2.).max(MIN_ORBIT_DISTANCE) * FRAME_MARGIN;
+    match projection {
+        Projection::Orthographic(orthographic) => {
+            if let ScalingMode::FixedVertical(view_height) = orthographic.scaling_mode {
+                orthographic.scale = (radius * 2. / view_height).clamp(MIN_ZOOM, MAX_ZOOM);
+            }
+            transform.translation.x = center.x;
+            transform.translation.z = center.z;
+        }
+        Projection::Perspective(perspective) => {
+            let distance = (radius / (perspective.fov / 2.).sin()).min(MAX_ORBIT_DISTANCE);
+            transform.translation = center - transform.forward() * distance;
+            orbit.focus = center;
+            orbit.distance = distance;
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn frame_view(
+    keys: Res<Input<KeyCode>>,
+    room: Res<Room>,
+    selection: Res<Selection>,
+    models: Query<(Entity, &Transform, &GlobalTransform), With<PlacedModel>>,
+    children: Query<&Children>,
+    meshes: Query<(&Aabb, &GlobalTransform)>,
+    mut orbit: ResMut<OrbitCamera>,
+    mut camera: Query<(&mut Transform, &mut Projection), (With<Camera3d>, Without<PlacedModel>)>,
+) {
+    if !keys.just_pressed(KeyCode::F) {
+        return;
+    }
+    let bounds = if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        Some(room_bounds(&room))
+    } else {
+        models
+            .iter_many(selection.models())
+            .filter_map(|(entity, transform, global)| {
+                let bounds = model_bounds(entity, global, &children, &meshes)?;
+                Some(world_bounds(bounds, transform))
+            })
+            .reduce(|(low, high), (min, max)| (low.min(min), high.max(max)))
+    };
+    let (Some(bounds), Ok((mut transform, mut projection))) = (bounds, camera.get_single_mut())
+    else {
+        return;
+    };
+    frame(bounds, &mut orbit, &mut transform, &mut projection);
+}
+
+fn orbit_controls(
+    mouse: Res<Input<MouseButton>>,
+    mut wheel_events: EventReader<MouseWheel>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    mut orbit: ResMut<OrbitCamera>,
+    mut camera: Query<(&mut Transform, &Projection), With<Camera3d>>,
+    mut orbit_drag: Local<CursorDrag>,
+    mut pan_drag: Local<CursorDrag>,
+) {
+    let (Ok(window), Ok((mut transform, Projection::Perspective(perspective)))) =
+        (window.get_single(), camera.get_single_mut())
+    else {
+        return;
+    };
+    let steps = wheel_steps(&mut wheel_events, window);
+    orbit.distance =
+        (orbit.distance * 0.9f32.powf(steps)).clamp(MIN_ORBIT_DISTANCE, MAX_ORBIT_DISTANCE);
+
+    let turn = orbit_drag.delta(mouse.pressed(MouseButton::Right), window) * ORBIT_SPEED;
+    orbit.yaw -= turn.x;
+    orbit.pitch = (orbit.pitch - turn.y).clamp(-FRAC_PI_2 + 0.01, FRAC_PI_2 - 0.01);
+
+    // Panning moves the focus with the cursor, at the focus's distance
+    let pan = pan_drag.delta(mouse.pressed(MouseButton::Middle), window);
+    let pixel = 2. * orbit.distance * (perspective.fov / 2.).tan() / window.height();
+    let offset = (transform.right() * pan.x - transform.up() * pan.y) * pixel;
+    orbit.focus -= offset;
+
+    *transform = orbit.transform();
+}
+
+#[allow(clippy::too_many_arguments)]
+fn plan_controls(
+    mouse: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+    time: Res<Time>,
+    mut wheel_events: EventReader<MouseWheel>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    mut camera: Query<(&mut Transform, &mut Projection), With<Camera3d>>,
+    mut drag: Local<CursorDrag>,
+) {
+    let (Ok(window), Ok((mut transform, mut projection))) =
+        (window.get_single(), camera.get_single_mut())
+    else {
+        return;
+    };
+    let Projection::Orthographic(orthographic) = &mut *projection else {
+        return;
+    };
+    let ScalingMode::FixedVertical(view_height) = orthographic.scaling_mode else {
+        return;
+    };
+    // World units per pixel at the current zoom
+    let pixel = |scale: f32| view_height * scale / window.height();
+    let (right, up) = (transform.right(), transform.up());
+    let screen_to_world = |offset: Vec2| right * offset.x - up * offset.y;
+
+    // Zooming keeps the point under the cursor in place
+    let steps = wheel_steps(&mut wheel_events, window);
+    if let (true, Some(cursor)) = (steps != 0., window.cursor_position()) {
+        let before = orthographic.scale;
+        orthographic.scale = (before * 0.9f32.powf(steps)).clamp(MIN_ZOOM, MAX_ZOOM);
+        let offset = cursor - Vec2::new(window.width(), window.height()) / 2.;
+        transform.translation +=
+            screen_to_world(offset) * (pixel(before) - pixel(orthographic.scale));
+    }
+
+    let panning = mouse.any_pressed([MouseButton::Right, MouseButton::Middle]);
+    transform.translation -=
+        screen_to_world(drag.delta(panning, window)) * pixel(orthographic.scale);
+
+    if keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+    let mut direction = Vec2::ZERO;
+    for (key, step) in [
+        (KeyCode::W, Vec2::NEG_Y),
+        (KeyCode::S, Vec2::Y),
+        (KeyCode::A, Vec2::NEG_X),
+        (KeyCode::D, Vec2::X),
+    ] {
+        if keys.pressed(key) {
+            direction += step;
+        }
+    }
+    transform.translation += screen_to_world(direction.normalize_or_zero())
+        * window.height()
+        * pixel(orthographic.scale)
+        * PAN_SPEED
+        * time.delta_seconds();
+}
+
+// The room outline along the top of the walls and an outline of each model's
+// footprint on top of it
+fn draw_plan(
+    room: Res<Room>,
+    selection: Res<Selection>,
+    models: Query<(Entity, &Transform, &GlobalTransform), With<PlacedModel>>,
+    children: Query<&Children>,
+    meshes: Query<(&Aabb, &GlobalTransform)>,
+    mut gizmos: Gizmos,
+) {
+    for (start, end) in room.walls() {
+        gizmos.line(
+            Vec3::new(start.x, room.wall_height, start.y),
+            Vec3::new(end.x, room.wall_height, end.y),
+            FOOTPRINT_COLOR,
+        );
+    }
+    for (entity, transform, global) in &models {
+        let Some((min, max)) = model_bounds(entity, global, &children, &meshes) else {
+            continue;
+        };
+        let corners = [
+            (min.x, min.z),
+            (max.x, min.z),
+            (max.x, max.z),
+            (min.x, max.z),
+        ]
+        .map(|(x, z)| transform.transform_point(Vec3::new(x, max.y, z)));
+        let color = if selection.contains(entity) {
+            SELECTED_FOOTPRINT_COLOR
+        } else {
+            FOOTPRINT_COLOR
+        };
+        gizmos.linestrip(corners.into_iter().chain([corners[0]]), color);
+    }
+}
@@ -0,0 +1,325 @@
+use bevy::{ecs::system::SystemParam, prelude::*, render::primitives::Aabb};
+
+use crate::{model_bounds, room::Room, LoadingState, PlacedModel};
+
+// Boxes have to overlap by more than this to count, so models snapped flush
+// against each other or a wall don't
+const TOLERANCE: f32 = 0.005;
+
+// Outlines models that overlap another model or stick through a wall. K
+// toggles solid mode, where models moved by dragging them or their handles
+// slide along whatever they hit instead of passing through, and Shift+K
+// switches between world-aligned and oriented boxes.
+pub struct CollisionPlugin;
+
+impl Plugin for CollisionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CollisionSettings>().add_systems(
+            Update,
+            (
+                collision_keys.run_if(in_state(LoadingState::Loaded)),
+                draw_overlaps,
+            ),
+        );
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum CollisionShape {
+    // The world-aligned box around each model, which is loose for rotated ones
+    #[default]
+    Aabb,
+    Oriented,
+}
+
+#[derive(Resource, Default)]
+pub struct CollisionSettings {
+    pub shape: CollisionShape,
+    pub solid: bool,
+}
+
+#[derive(Clone, Copy)]
+pub struct ModelBox {
+    center: Vec3,
+    axes: [Vec3; 3],
+    half_extents: Vec3,
+}
+
+impl ModelBox {
+    pub fn new(bounds: (Vec3, Vec3), transform: &Transform, shape: CollisionShape) -> Self {
+        let (min, max) = bounds;
+        let center = transform.transform_point((min + max) / 2.);
+        let half_extents = ((max - min) / 2. * transform.scale.abs() - TOLERANCE).max(Vec3::ZERO);
+        let axes = [
+            transform.rotation * Vec3::X,
+            transform.rotation * Vec3::Y,
+            transform.rotation * Vec3::Z,
+        ];
+        match shape {
+            CollisionShape::Oriented => Self {
+                center,
+                axes,
+                half_extents,
+            },
+            CollisionShape::Aabb => Self {
+                center,
+                axes: [Vec3::X, Vec3::Y, Vec3::Z],
+                half_extents: axes
+                    .iter()
+                    .zip(half_extents.to_array())
+                    .map(|(axis, half)| axis.abs() * half)
+                    .sum(),
+            },
+        }
+    }
+
+    pub fn translated(mut self, offset: Vec3) -> Self {
+        self.center += offset;
+        self
+    }
+
+    // Half the length of the box's shadow on `axis`
+    fn radius(&self, axis: Vec3) -> f32 {
+        self.axes
+            .iter()
+            .zip(self.half_extents.to_array())
+            .map(|(own, half)| own.dot(axis).abs() * half)
+            .sum()
+    }
+
+    // Separating axis test: the boxes are apart if their shadows on any face
+    // normal or cross product of edges don't overlap
+    pub fn overlaps(&self, other: &ModelBox) -> bool {
+        let offset = other.center - self.center;
+        let edges = self
+            .axes
+            .iter()
+            .flat_map(|a| other.axes.iter().map(move |b| a.cross(*b)));
+        self.axes
+            .into_iter()
+            .chain(other.axes)
+            .chain(edges)
+            .filter_map(|axis| axis.try_normalize())
+            .all(|axis| offset.dot(axis).abs() <= self.radius(axis) + other.radius(axis))
+    }
+
+    fn corners(&self) -> impl Iterator<Item = Vec3> + '_ {
+        (0..8).map(|corner| {
+            let [x, y, z] = [1, 2, 4].map(|bit| if corner & bit == 0 { -1. } else { 1. });
+            self.center
+                + self.axes[0] * x * self.half_extents.x
+                + self.axes[1] * y * self.half_extents.y
+                + self.axes[2] * z * self.half_extents.z
+        })
+    }
+
+    // Whether the box reaches outside the floor outline, or a corner of a
+    // non-convex room pokes into it
+    pub fn crosses_walls(&self, room: &Room) -> bool {
+        if self.corners().any(|corner| !room.contains(corner.xz())) {
+            return true;
+        }
+        room.corners().into_iter().any(|corner| {
+            let offset = Vec3::new(corner.x, self.center.y, corner.y) - self.center;
+            self.axes
+                .iter()
+                .zip(self.half_extents.to_array())
+                .all(|(axis, half)| offset.dot(*axis).abs() < half)
+        })
+    }
+
+    fn outline(&self) -> Transform {
+        Transform {
+            translation: self.center,
+            rotation: Quat::from_mat3(&Mat3::from_cols(self.axes[0], self.axes[1], self.axes[2])),
+            scale: self.half_extents * 2.,
+        }
+    }
+}
+
+// Tries the move, then each of its horizontal components on its own so the
+// models slide along what they hit, then gives up. Models that are already
+// stuck can move freely to get out.
+pub fn slide(delta: Vec3, moving: &[ModelBox], obstacles: &[ModelBox], room: &Room) -> Vec3 {
+    let blocked = |offset: Vec3| {
+        moving.iter().any(|model| {
+            let model = model.translated(offset);
+            model.crosses_walls(room) || obstacles.iter().any(|other| model.overlaps(other))
+        })
+    };
+    if blocked(Vec3::ZERO) {
+        return delta;
+    }
+    [
+        delta,
+        Vec3::new(delta.x, delta.y, 0.),
+        Vec3::new(0., delta.y, delta.z),
+    ]
+    .into_iter()
+    .find(|offset| !blocked(*offset))
+    .unwrap_or(Vec3::ZERO)
+}
+
+// Solid mode checks for moves made by dragging models or their handles
+#[derive(SystemParam)]
+pub struct Solid<'w, 's> {
+    settings: Res<'w, CollisionSettings>,
+    room: Res<'w, Room>,
+    models: Query<'w, 's, (Entity, &'static GlobalTransform), With<PlacedModel>>,
+    children: Query<'w, 's, &'static Children>,
+    meshes: Query<'w, 's, (&'static Aabb, &'static GlobalTransform)>,
+}
+
+impl Solid<'_, '_> {
+    // The boxes of the `moving` models at the given transforms, and of every
+    // other placed model where it is
+    fn boxes(&self, moving: &[(Entity, Transform)]) -> (Vec<ModelBox>, Vec<ModelBox>) {
+        let mut moved = Vec::new();
+        let mut obstacles = Vec::new();
+        for (entity, global) in &self.models {
+            let Some(bounds) = model_bounds(entity, global, &self.children, &self.meshes) else {
+                continue;
+            };
+            match moving.iter().find(|(model, _)| *model == entity) {
+                Some((_, transform)) => {
+                    moved.push(ModelBox::new(bounds, transform, self.settings.shape));
+                }
+                None => obstacles.push(ModelBox::new(
+                    bounds,
+                    &global.compute_transform(),
+                    self.settings.shape,
+                )),
+            }
+        }
+        (moved, obstacles)
+    }
+
+    // How far the models can move by `delta`, see `slide`
+    pub fn slide(&self, delta: Vec3, moving: &[(Entity, Transform)]) -> Vec3 {
+        if !self.settings.solid {
+            return delta;
+        }
+        let (moved, obstacles) = self.boxes(moving);
+        slide(delta, &moved, &obstacles, &self.room)
+    }
+
+    // Whether changing the models from the `from` to the `to` transforms
+    // runs them into a wall or another model. Models that are already stuck
+    // can change freely to get out.
+    pub fn blocks(&self, from: &[(Entity, Transform)], to: &[(Entity, Transform)]) -> bool {
+        if !self.settings.solid {
+            return false;
+        }
+        let blocked = |moving: &[(Entity, Transform)]| {
+            let (moved, obstacles) = self.boxes(moving);
+            moved.iter().any(|model| {
+                model.crosses_walls(&self.room)
+                    || obstacles.iter().any(|other| model.overlaps(other))
+            })
+        };
+        blocked(to) && !blocked(from)
+    }
+}
+
+fn collision_keys(keys: Res<Input<KeyCode>>, mut settings: ResMut<CollisionSettings>) {
+    if !keys.just_pressed(KeyCode::K) {
+        return;
+    }
+    if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        settings.shape = match settings.shape {
+            CollisionShape::Aabb => CollisionShape::Oriented,
+            CollisionShape::Oriented => CollisionShape::Aabb,
+        };
+        info!("Collision boxes: {:?}", settings.shape);
+    } else {
+        settings.solid = !settings.solid;
+        info!("Solid models {}", if settings.solid { "on" } else { "off" });
+    }
+}
+
+// The collision box of every placed model whose scene has spawned
+pub fn model_boxes(
+    settings: &CollisionSettings,
+    models: impl Iterator<Item = (Entity, Transform, GlobalTransform)>,
+    children: &Query<&Children>,
+    meshes: &Query<(&Aabb, &GlobalTransform)>,
+) -> Vec<(Entity, ModelBox)> {
+    models
+        .filter_map(|(entity, transform, global)| {
+            let bounds = model_bounds(entity, &global, children, meshes)?;
+            Some((entity, ModelBox::new(bounds, &transform, settings.shape)))
+        })
+        .collect()
+}
+
+fn draw_overlaps(
+    settings: Res<CollisionSettings>,
+    room: Res<Room>,
+    models: Query<(Entity, &Transform, &GlobalTransform), With<PlacedModel>>,
+    children: Query<&Children>,
+    meshes: Query<(&Aabb, &GlobalTransform)>,
+    mut gizmos: Gizmos,
+) {
+    let boxes = model_boxes(
+        &settings,
+        models
+            .iter()
+            .map(|(entity, transform, global)| (entity, *transform, *global)),
+        &children,
+        &meshes,
+    );
+    for (index, (_, model)) in boxes.iter().enumerate() {
+        let overlapping = model.crosses_walls(&room)
+            || boxes
+                .iter()
+                .enumerate()
+                .any(|(other, (_, other_model))| other != index && model.overlaps(other_model));
+        if overlapping {
+            gizmos.cuboid(model.outline(), Color::RED);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_4;
+
+    use super::*;
+
+    const UNIT: (Vec3, Vec3) = (Vec3::splat(-0.5), Vec3::splat(0.5));
+
+    fn cube(x: f32, z: f32, yaw: f32, shape: CollisionShape) -> ModelBox {
+        let transform = Transform::from_xyz(x, 0., z).with_rotation(Quat::from_rotation_y(yaw));
+        ModelBox::new(UNIT, &transform, shape)
+    }
+
+    #[test]
+    fn separates_boxes_apart() {
+        let a = cube(0., 0., 0., CollisionShape::Oriented);
+        assert!(a.overlaps(&cube(0.9, 0., 0., CollisionShape::Oriented)));
+        assert!(!a.overlaps(&cube(1.1, 0., 0., CollisionShape::Oriented)));
+    }
+
+    #[test]
+    fn flush_boxes_do_not_overlap() {
+        let a = cube(0., 0., 0., CollisionShape::Oriented);
+        assert!(!a.overlaps(&cube(1., 0., 0., CollisionShape::Oriented)));
+    }
+
+    #[test]
+    fn rotated_boxes_use_their_own_axes() {
+        // A cube turned 45 degrees diagonally next to another clears its
+        // corner, but its loose world-aligned box doesn't
+        let a = cube(0., 0., 0., CollisionShape::Oriented);
+        assert!(!a.overlaps(&cube(0.9, 0.9, FRAC_PI_4, CollisionShape::Oriented)));
+        let a = cube(0., 0., 0., CollisionShape::Aabb);
+        assert!(a.overlaps(&cube(0.9, 0.9, FRAC_PI_4, CollisionShape::Aabb)));
+    }
+
+    #[test]
+    fn crosses_walls() {
+        let room = Room::rectangle(4., 4., 2.5);
+        assert!(!cube(0., 0., 0., CollisionShape::Aabb).crosses_walls(&room));
+        assert!(cube(1.8, 0., 0., CollisionShape::Aabb).crosses_walls(&room));
+    }
+}
@@ -0,0 +1,105 @@
+use bevy::prelude::*;
+
+use crate::{
+    camera::room_center,
+    room::{closest_point_on_segment, Room, RoomWall},
+    LoadingState,
+};
+
+// Walls closer to the camera than this are cut away even when they aren't
+// between it and the room, so the view isn't clipped by a wall right behind
+// or beside it
+const NEAR_WALL: f32 = 0.5;
+
+// Hides walls that would block the view into the room. H cycles between
+// showing every wall, cutting away the walls between the camera and the
+// middle of the room, and hiding every wall. Hidden walls are also skipped when
+// clicking on walls, see `HiddenWalls`.
+pub struct CutawayPlugin;
+
+impl Plugin for CutawayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WallDisplay>()
+            .init_resource::<HiddenWalls>()
+            .add_systems(
+                Update,
+                (
+                    switch_wall_display.run_if(in_state(LoadingState::Loaded)),
+                    update_hidden_walls,
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WallDisplay {
+    Up,
+    #[default]
+    Cutaway,
+    Down,
+}
+
+// Indices of the walls that are hidden right now
+#[derive(Resource, Default)]
+pub struct HiddenWalls(pub Vec<usize>);
+
+fn switch_wall_display(keys: Res<Input<KeyCode>>, mut wall_display: ResMut<WallDisplay>) {
+    if !keys.just_pressed(KeyCode::H) {
+        return;
+    }
+    *wall_display = match *wall_display {
+        WallDisplay::Up => WallDisplay::Cutaway,
+        WallDisplay::Cutaway => WallDisplay::Down,
+        WallDisplay::Down => WallDisplay::Up,
+    };
+    info!("Walls: {:?}", *wall_display);
+}
+
+// Whether the segments from `a` to `b` and from `c` to `d` cross
+fn segments_cross(a: Vec2, b: Vec2, c: Vec2, d: Vec2) -> bool {
+    let side = |from: Vec2, to: Vec2, point: Vec2| (to - from).perp_dot(point - from);
+    side(a, b, c) * side(a, b, d) < 0. && side(c, d, a) * side(c, d, b) < 0.
+}
+
+// A wall is in the way when it crosses the line from the camera to the
+// middle of the room, or the camera is right next to it
+fn update_hidden_walls(
+    wall_display: Res<WallDisplay>,
+    room: Res<Room>,
+    camera: Query<&GlobalTransform, With<Camera3d>>,
+    mut walls: Query<(&RoomWall, &mut Visibility)>,
+    mut hidden: ResMut<HiddenWalls>,
+) {
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+    let eye = camera.translation().xz();
+    let center = room_center(&room).xz();
+    let now_hidden: Vec<usize> = room
+        .walls()
+        .enumerate()
+        .filter(|(_, (start, end))| match *wall_display {
+            WallDisplay::Up => false,
+            WallDisplay::Cutaway => {
+                segments_cross(eye, center, *start, *end)
+                    || eye.distance(closest_point_on_segment(eye, *start, *end)) < NEAR_WALL
+            }
+            WallDisplay::Down => true,
+        })
+        .map(|(wall, _)| wall)
+        .collect();
+    for (wall, mut visibility) in &mut walls {
+        let shown = if now_hidden.contains(&wall.0) {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+        if *visibility != shown {
+            *visibility = shown;
+        }
+    }
+    if hidden.0 != now_hidden {
+        hidden.0 = now_hidden;
+    }
+}
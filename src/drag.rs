@@ -0,0 +1,193 @@
+use bevy::{prelude::*, render::primitives::Aabb, utils::HashMap};
+use bevy_mod_picking::prelude::*;
+
+use crate::{
+    camera::CameraMode, collision::Solid, history::GestureEndEvent, model_bounds, room::Room,
+    selection::Selection, snap::SnapSettings, surface::Surfaces, PlacedModel,
+};
+
+#[derive(Event)]
+pub struct ModelMoveEvent {
+    pub model: Entity,
+    pub drag: Drag,
+    pub position: Vec2,
+}
+
+impl From<ListenerInput<Pointer<Drag>>> for ModelMoveEvent {
+    fn from(value: ListenerInput<Pointer<Drag>>) -> Self {
+        Self {
+            model: value.listener(),
+            drag: (**value).clone(),
+            position: value.pointer_location.position,
+        }
+    }
+}
+
+// The plane a model is dragged along and where on that plane it was grabbed,
+// so the grabbed point stays under the cursor for the whole gesture
+pub struct Grab {
+    button: PointerButton,
+    plane_origin: Vec3,
+    plane_normal: Vec3,
+    offset: Vec3,
+}
+
+impl Grab {
+    fn new(
+        button: PointerButton,
+        model: &Transform,
+        camera: &Camera,
+        camera_transform: &GlobalTransform,
+        position: Vec2,
+    ) -> Option<Self> {
+        let plane_normal = match button {
+            // Lifting uses a vertical plane facing the camera
+            PointerButton::Middle => {
+                let back = camera_transform.back();
+                Vec3::new(back.x, 0., back.z).try_normalize()?
+            }
+            _ => Vec3::Y,
+        };
+        let mut grab = Self {
+            button,
+            plane_origin: model.translation,
+            plane_normal,
+            offset: Vec3::ZERO,
+        };
+        grab.offset = model.translation - grab.hit(camera, camera_transform, position)?;
+        Some(grab)
+    }
+
+    fn hit(
+        &self,
+        camera: &Camera,
+        camera_transform: &GlobalTransform,
+        position: Vec2,
+    ) -> Option<Vec3> {
+        let ray = camera.viewport_to_world(camera_transform, position)?;
+        let distance = ray.intersect_plane(self.plane_origin, self.plane_normal)?;
+        Some(ray.get_point(distance))
+    }
+}
+
+// Dragging a selected model moves the whole selection by the grabbed model's
+// delta. The primary button moves along the floor and the middle one lifts,
+// except in the orbit and plan views where it pans the camera.
+// The grabbed model snaps to the grid and, when moved alone, against walls,
+// and models moved along the floor rest on top of whatever is below them.
+// In solid mode the models slide along obstacles instead of passing through.
+#[allow(clippy::too_many_arguments)]
+pub fn move_model(
+    mut models: Query<&mut Transform, (With<PlacedModel>, Without<Camera3d>)>,
+    model_transforms: Query<(Entity, &GlobalTransform), With<PlacedModel>>,
+    children: Query<&Children>,
+    meshes: Query<(&Aabb, &GlobalTransform)>,
+    camera: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+    keys: Res<Input<KeyCode>>,
+    camera_mode: Res<CameraMode>,
+    snap: Res<SnapSettings>,
+    solid: Solid,
+    room: Res<Room>,
+    surfaces: Surfaces,
+    selection: Res<Selection>,
+    mut move_events: EventReader<ModelMoveEvent>,
+    mut end_events: EventReader<GestureEndEvent>,
+    mut grabs: Local<HashMap<Entity, Grab>>,
+) {
+    let Ok((camera, camera_transform)) = camera.get_single() else {
+        error!("No camera!?");
+        return;
+    };
+    for event in move_events.read() {
+        let Ok(model) = models.get(event.model) else {
+            error!("Event for nonexistent model");
+            continue;
+        };
+        let model = *model;
+        let group = selection.group(event.model);
+        let button = event.drag.button;
+        // Rotating and scaling are done with the handles, see `gizmo`
+        if button == PointerButton::Secondary
+            || (button == PointerButton::Middle && camera_mode.pans_with_middle())
+        {
+            continue;
+        }
+        if !matches!(grabs.get(&event.model), Some(grab) if grab.button == button) {
+            // The drag event is sent after the pointer has already moved, so the
+            // grab is taken from where the pointer was before this delta
+            let start = event.position - event.drag.delta;
+            let Some(grab) = Grab::new(button, &model, camera, camera_transform, start) else {
+                continue;
+            };
+            grabs.insert(event.model, grab);
+        }
+        let grab = &grabs[&event.model];
+        let Some(hit) = grab.hit(camera, camera_transform, event.position) else {
+            continue;
+        };
+        let mut target = hit + grab.offset;
+        if SnapSettings::is_active(&keys) {
+            target = Vec3::new(
+                snap.position(target.x),
+                snap.position(target.y),
+                snap.position(target.z),
+            );
+        }
+        let mut delta = target - model.translation;
+        match button {
+            PointerButton::Primary => delta.y = 0.,
+            _ => delta = Vec3::new(0., delta.y, 0.),
+        }
+        let moving: Vec<(Entity, Transform)> = group
+            .iter()
+            .filter_map(|entity| Some((*entity, *models.get(*entity).ok()?)))
+            .collect();
+        delta = solid.slide(delta, &moving);
+        // Only a model moved on its own is turned to face away from a wall
+        if button == PointerButton::Primary && group.len() == 1 && SnapSettings::is_active(&keys) {
+            let wall = model_transforms
+                .get(event.model)
+                .ok()
+                .and_then(|(_, global)| model_bounds(event.model, global, &children, &meshes))
+                .and_then(|bounds| {
+                    snap.wall(&room, model.translation + delta, bounds, model.scale)
+                });
+            // In solid mode the snap can't push the model into another one
+            let wall = wall.filter(|(translation, rotation)| {
+                let moved = model.with_translation(model.translation + delta);
+                let snapped = Transform {
+                    translation: *translation,
+                    rotation: *rotation,
+                    ..model
+                };
+                !solid.blocks(&[(event.model, moved)], &[(event.model, snapped)])
+            });
+            if let (Some((translation, rotation)), Ok(mut model)) =
+                (wall, models.get_mut(event.model))
+            {
+                model.translation = translation;
+                model.rotation = rotation;
+                delta = Vec3::ZERO;
+            }
+        }
+        let mut iter = models.iter_many_mut(&group);
+        while let Some(mut transform) = iter.fetch_next() {
+            transform.translation += delta;
+        }
+        // Moving along the floor keeps each model resting on what is below it
+        if button == PointerButton::Primary {
+            for (entity, global) in model_transforms.iter_many(&group) {
+                let (Some(bounds), Ok(mut transform)) = (
+                    model_bounds(entity, global, &children, &meshes),
+                    models.get_mut(entity),
+                ) else {
+                    continue;
+                };
+                transform.translation.y = surfaces.resting_height(&transform, bounds, &group);
+            }
+        }
+    }
+    for GestureEndEvent(entity) in end_events.read() {
+        grabs.remove(entity);
+    }
+}
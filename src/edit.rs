@@ -0,0 +1,218 @@
+use bevy::{gltf::Gltf, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    history::{EditCommand, History, ModelSnapshot},
+    layout::ModelRecord,
+    selection::Selection,
+    spawn_placed_model, LoadedModelList, LoadingState, PlacedModel,
+};
+
+const DUPLICATE_OFFSET: Vec3 = Vec3::new(2., 0., 2.);
+const CLIPBOARD_FORMAT: &str = "deco-models";
+const CLIPBOARD_VERSION: u32 = 1;
+
+pub struct EditPlugin;
+
+impl Plugin for EditPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_non_send_resource(ModelClipboard::default())
+            .add_systems(
+                Update,
+                (delete_selected, duplicate_selected, copy_paste)
+                    .run_if(in_state(LoadingState::Loaded)),
+            );
+    }
+}
+
+// Kept alive for the whole session because on X11 copied text is only
+// available while the clipboard handle that set it still exists
+struct ModelClipboard {
+    system: Option<arboard::Clipboard>,
+    fallback: String,
+}
+
+impl Default for ModelClipboard {
+    fn default() -> Self {
+        let system = arboard::Clipboard::new()
+            .map_err(|err| {
+                warn!("System clipboard unavailable, copying within this session only: {err}")
+            })
+            .ok();
+        Self {
+            system,
+            fallback: String::new(),
+        }
+    }
+}
+
+impl ModelClipboard {
+    fn set(&mut self, text: String) {
+        if let Some(clipboard) = &mut self.system {
+            if let Err(err) = clipboard.set_text(text.clone()) {
+                warn!("Unable to write to the system clipboard: {err}");
+            }
+        }
+        self.fallback = text;
+    }
+
+    fn get(&mut self) -> String {
+        self.system
+            .as_mut()
+            .and_then(|clipboard| clipboard.get_text().ok())
+            .unwrap_or_else(|| self.fallback.clone())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ClipboardContents {
+    format: String,
+    version: u32,
+    models: Vec<ModelRecord>,
+}
+
+fn ctrl_pressed(keys: &Input<KeyCode>) -> bool {
+    keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight])
+}
+
+fn spawn_copies(
+    commands: &mut Commands,
+    gltf_assets: &Assets<Gltf>,
+    copies: impl IntoIterator<Item = (Handle<Gltf>, Transform)>,
+) -> Vec<EditCommand> {
+    copies
+        .into_iter()
+        .filter_map(|(model, mut transform)| {
+            transform.translation += DUPLICATE_OFFSET;
+            let entity = spawn_placed_model(commands, gltf_assets, model.clone(), transform)?;
+            Some(EditCommand::Spawn {
+                entity,
+                snapshot: ModelSnapshot { model, transform },
+            })
+        })
+        .collect()
+}
+
+// The copies are selected so they can be moved away from the originals
+fn select_spawned(selection: &mut Selection, spawned: &[EditCommand]) {
+    selection.set(
+        spawned
+            .iter()
+            .filter_map(|command| match command {
+                EditCommand::Spawn { entity, .. } => Some(*entity),
+                _ => None,
+            })
+            .collect(),
+    );
+}
+
+fn delete_selected(
+    keys: Res<Input<KeyCode>>,
+    mut commands: Commands,
+    selection: Res<Selection>,
+    models: Query<(&PlacedModel, &Transform)>,
+    mut history: ResMut<History>,
+) {
+    // Shift+Delete removes wall openings instead, see `openings`
+    if !keys.just_pressed(KeyCode::Delete)
+        || keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight])
+    {
+        return;
+    }
+    let mut deleted = Vec::new();
+    for &entity in selection.models() {
+        let Ok((model, transform)) = models.get(entity) else {
+            continue;
+        };
+        deleted.push(EditCommand::Delete {
+            entity,
+            snapshot: ModelSnapshot {
+                model: model.0.clone(),
+                transform: *transform,
+            },
+        });
+        commands.entity(entity).despawn_recursive();
+    }
+    if !deleted.is_empty() {
+        history.push(EditCommand::Batch(deleted));
+    }
+}
+
+fn duplicate_selected(
+    keys: Res<Input<KeyCode>>,
+    mut commands: Commands,
+    mut selection: ResMut<Selection>,
+    models: Query<(&PlacedModel, &Transform)>,
+    gltf_assets: Res<Assets<Gltf>>,
+    mut history: ResMut<History>,
+) {
+    if !ctrl_pressed(&keys) || !keys.just_pressed(KeyCode::D) {
+        return;
+    }
+    let copies = selection
+        .models()
+        .iter()
+        .filter_map(|entity| models.get(*entity).ok())
+        .map(|(model, transform)| (model.0.clone(), *transform))
+        .collect::<Vec<_>>();
+    let spawned = spawn_copies(&mut commands, &gltf_assets, copies);
+    if !spawned.is_empty() {
+        select_spawned(&mut selection, &spawned);
+        history.push(EditCommand::Batch(spawned));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn copy_paste(
+    keys: Res<Input<KeyCode>>,
+    mut commands: Commands,
+    mut selection: ResMut<Selection>,
+    models: Query<(&PlacedModel, &Transform)>,
+    model_list: Res<LoadedModelList>,
+    gltf_assets: Res<Assets<Gltf>>,
+    mut history: ResMut<History>,
+    mut clipboard: NonSendMut<ModelClipboard>,
+) {
+    if !ctrl_pressed(&keys) {
+        return;
+    }
+    if keys.just_pressed(KeyCode::C) {
+        let contents = ClipboardContents {
+            format: CLIPBOARD_FORMAT.to_string(),
+            version: CLIPBOARD_VERSION,
+            models: selection
+                .models()
+                .iter()
+                .filter_map(|entity| models.get(*entity).ok())
+                .filter_map(|(model, transform)| ModelRecord::new(model, transform))
+                .collect(),
+        };
+        if contents.models.is_empty() {
+            return;
+        }
+        match ron::to_string(&contents) {
+            Ok(text) => clipboard.set(text),
+            Err(err) => error!("Unable to copy models: {err}"),
+        }
+    }
+    if keys.just_pressed(KeyCode::V) {
+        let Ok(contents) = ron::from_str::<ClipboardContents>(&clipboard.get()) else {
+            return;
+        };
+        if contents.format != CLIPBOARD_FORMAT || contents.version > CLIPBOARD_VERSION {
+            return;
+        }
+        let mut copies = Vec::new();
+        for record in contents.models {
+            match model_list.find_by_path(&record.path) {
+                Some(model) => copies.push((model.clone(), record.transform.into())),
+                None => warn!("Unable to paste {}: model is not loaded", record.path),
+            }
+        }
+        let spawned = spawn_copies(&mut commands, &gltf_assets, copies);
+        if !spawned.is_empty() {
+            select_spawned(&mut selection, &spawned);
+            history.push(EditCommand::Batch(spawned));
+        }
+    }
+}
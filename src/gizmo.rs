@@ -0,0 +1,489 @@
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::prelude::*;
+use bevy_mod_picking::prelude::*;
+
+use crate::{
+    camera::view_scale,
+    collision::Solid,
+    drag::move_model,
+    history::{record_gesture_end, record_gesture_start, GestureEndEvent, GestureStartEvent},
+    metadata::Metadata,
+    selection::Selection,
+    snap::{yaw, SnapSettings},
+    LoadingState, PlacedModel,
+};
+
+// Size of the handles relative to their distance from the camera, so they
+// stay the same size on screen
+const GIZMO_SCALE: f32 = 0.15;
+const MIN_SCALE: f32 = 0.01;
+
+// Translate, rotate and scale handles around the selection. T, Y and U switch
+// between them and each handle constrains its drag to one axis.
+pub struct TransformGizmoPlugin;
+
+impl Plugin for TransformGizmoPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GizmoMode>()
+            .add_systems(Startup, spawn_gizmo)
+            .add_systems(
+                Update,
+                (
+                    switch_gizmo_mode.run_if(in_state(LoadingState::Loaded)),
+                    handle_gestures.before(record_gesture_start),
+                    drag_handles
+                        .after(record_gesture_start)
+                        .before(move_model)
+                        .before(record_gesture_end),
+                    update_gizmo.after(move_model),
+                ),
+            );
+    }
+}
+
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GizmoMode {
+    #[default]
+    Translate,
+    Rotate,
+    Scale,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum GizmoAxis {
+    X,
+    Y,
+    Z,
+    // Uniform scale
+    All,
+}
+
+impl GizmoAxis {
+    fn index(self) -> Option<usize> {
+        match self {
+            GizmoAxis::X => Some(0),
+            GizmoAxis::Y => Some(1),
+            GizmoAxis::Z => Some(2),
+            GizmoAxis::All => None,
+        }
+    }
+
+    fn direction(self, rotation: Quat) -> Vec3 {
+        rotation
+            * match self {
+                GizmoAxis::X => Vec3::X,
+                GizmoAxis::Y => Vec3::Y,
+                GizmoAxis::Z => Vec3::Z,
+                GizmoAxis::All => Vec3::ZERO,
+            }
+    }
+
+    // Turns a mesh built along Y to point along the axis
+    fn orientation(self) -> Quat {
+        match self {
+            GizmoAxis::X => Quat::from_rotation_z(-FRAC_PI_2),
+            GizmoAxis::Z => Quat::from_rotation_x(FRAC_PI_2),
+            _ => Quat::IDENTITY,
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            GizmoAxis::X => Color::rgb(0.9, 0.2, 0.2),
+            GizmoAxis::Y => Color::rgb(0.2, 0.8, 0.2),
+            GizmoAxis::Z => Color::rgb(0.2, 0.4, 0.9),
+            GizmoAxis::All => Color::rgb(0.9, 0.9, 0.9),
+        }
+    }
+}
+
+#[derive(Component)]
+struct TransformGizmo;
+
+#[derive(Component)]
+struct ModeHandles(GizmoMode);
+
+#[derive(Component)]
+struct GizmoHandle {
+    mode: GizmoMode,
+    axis: GizmoAxis,
+}
+
+fn spawn_gizmo(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let mut material = |axis: GizmoAxis| {
+        materials.add(StandardMaterial {
+            base_color: axis.color(),
+            unlit: true,
+            ..default()
+        })
+    };
+    let axes = [GizmoAxis::X, GizmoAxis::Y, GizmoAxis::Z];
+    let mut handles = Vec::new();
+    let arrow = meshes.add(
+        shape::Capsule {
+            radius: 0.05,
+            depth: 0.9,
+            ..default()
+        }
+        .into(),
+    );
+    for axis in axes {
+        let transform = Transform::from_rotation(axis.orientation())
+            .with_translation(axis.direction(Quat::IDENTITY) * 0.65);
+        handles.push((GizmoMode::Translate, axis, arrow.clone(), transform));
+    }
+    let ring = meshes.add(
+        shape::Torus {
+            radius: 1.1,
+            ring_radius: 0.05,
+            ..default()
+        }
+        .into(),
+    );
+    handles.push((GizmoMode::Rotate, GizmoAxis::Y, ring, Transform::IDENTITY));
+    let cube = meshes.add(shape::Cube { size: 0.2 }.into());
+    for axis in axes {
+        let transform = Transform::from_translation(axis.direction(Quat::IDENTITY));
+        handles.push((GizmoMode::Scale, axis, cube.clone(), transform));
+    }
+    handles.push((
+        GizmoMode::Scale,
+        GizmoAxis::All,
+        cube,
+        Transform::from_scale(Vec3::splat(1.3)),
+    ));
+
+    let materials = [GizmoAxis::X, GizmoAxis::Y, GizmoAxis::Z, GizmoAxis::All]
+        .map(|axis| (axis, material(axis)));
+    commands
+        .spawn((
+            SpatialBundle {
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            TransformGizmo,
+        ))
+        .with_children(|parent| {
+            for mode in [GizmoMode::Translate, GizmoMode::Rotate, GizmoMode::Scale] {
+                parent
+                    .spawn((SpatialBundle::default(), ModeHandles(mode)))
+                    .with_children(|parent| {
+                        for (_, axis, mesh, transform) in
+                            handles.iter().filter(|(m, ..)| *m == mode)
+                        {
+                            let (_, material) = materials
+                                .iter()
+                                .find(|(a, _)| a == axis)
+                                .expect("every axis has a material");
+                            parent.spawn((
+                                PbrBundle {
+                                    mesh: mesh.clone(),
+                                    material: material.clone(),
+                                    transform: *transform,
+                                    ..default()
+                                },
+                                PickableBundle::default(),
+                                GizmoHandle { mode, axis: *axis },
+                            ));
+                        }
+                    });
+            }
+        });
+}
+
+fn switch_gizmo_mode(keys: Res<Input<KeyCode>>, mut mode: ResMut<GizmoMode>) {
+    if keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+    let new_mode = if keys.just_pressed(KeyCode::T) {
+        GizmoMode::Translate
+    } else if keys.just_pressed(KeyCode::Y) {
+        GizmoMode::Rotate
+    } else if keys.just_pressed(KeyCode::U) {
+        GizmoMode::Scale
+    } else {
+        return;
+    };
+    if *mode != new_mode {
+        info!("{new_mode:?} mode");
+        *mode = new_mode;
+    }
+}
+
+// Handle drags are recorded in the history as gestures on the selection
+fn handle_gestures(
+    mut drag_start_events: EventReader<Pointer<DragStart>>,
+    mut drag_end_events: EventReader<Pointer<DragEnd>>,
+    handles: Query<(), With<GizmoHandle>>,
+    selection: Res<Selection>,
+    mut start_events: EventWriter<GestureStartEvent>,
+    mut end_events: EventWriter<GestureEndEvent>,
+) {
+    let model = selection.models().first().copied();
+    for event in drag_start_events.read() {
+        if let (true, Some(model)) = (handles.contains(event.target), model) {
+            start_events.send(GestureStartEvent(model));
+        }
+    }
+    for event in drag_end_events.read() {
+        if let (true, Some(model)) = (handles.contains(event.target), model) {
+            end_events.send(GestureEndEvent(model));
+        }
+    }
+}
+
+// Where along the line through `origin` the ray passes closest
+fn axis_position(ray: Ray, origin: Vec3, axis: Vec3) -> Option<f32> {
+    let along = axis.dot(ray.direction);
+    let denominator = 1. - along * along;
+    if denominator < 1e-4 {
+        // The axis points straight at the camera
+        return None;
+    }
+    let offset = origin - ray.origin;
+    Some((along * ray.direction.dot(offset) - axis.dot(offset)) / denominator)
+}
+
+// How far a handle has been dragged. Snapping rounds the first selected
+// model's position or angle, so the unrounded amount is kept separately.
+#[derive(Default)]
+struct HandleDrag {
+    start: f32,
+    dragged: f32,
+    applied: f32,
+}
+
+impl HandleDrag {
+    // The change to apply for the latest step of the drag
+    fn step(&mut self, step: f32, snap: Option<impl Fn(f32) -> f32>) -> f32 {
+        self.dragged += step;
+        let target = match snap {
+            Some(snap) => snap(self.start + self.dragged) - self.start,
+            None => self.dragged,
+        };
+        let change = target - self.applied;
+        self.applied = target;
+        change
+    }
+
+    // Takes back part of a step that wasn't applied
+    fn undo(&mut self, change: f32) {
+        self.applied -= change;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn drag_handles(
+    mut drag_start_events: EventReader<Pointer<DragStart>>,
+    mut drag_events: EventReader<Pointer<Drag>>,
+    handles: Query<&GizmoHandle>,
+    gizmo: Query<&Transform, (With<TransformGizmo>, Without<PlacedModel>)>,
+    mut models: Query<(Entity, &mut Transform, &PlacedModel), Without<Camera3d>>,
+    camera: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+    selection: Res<Selection>,
+    keys: Res<Input<KeyCode>>,
+    snap: Res<SnapSettings>,
+    solid: Solid,
+    mut metadata: Metadata,
+    mut drag: Local<HandleDrag>,
+) {
+    let (Ok(gizmo), Ok((camera, camera_transform))) = (gizmo.get_single(), camera.get_single())
+    else {
+        return;
+    };
+    let pivot = gizmo.translation;
+    let snapping = SnapSettings::is_active(&keys);
+    for event in drag_start_events.read() {
+        let (Ok(handle), Some(first)) = (
+            handles.get(event.target),
+            selection
+                .models()
+                .first()
+                .and_then(|first| models.get(*first).ok())
+                .map(|(_, transform, _)| transform),
+        ) else {
+            continue;
+        };
+        if handle.mode == GizmoMode::Scale
+            && models
+                .iter_many(selection.models())
+                .any(|(_, _, model)| metadata.get(&model.0).true_scale)
+        {
+            info!("Models locked to their true scale won't be scaled, press L to unlock them");
+        }
+        let start = match handle.mode {
+            GizmoMode::Translate => first.translation.dot(handle.axis.direction(gizmo.rotation)),
+            GizmoMode::Rotate => yaw(first.rotation),
+            GizmoMode::Scale => 0.,
+        };
+        *drag = HandleDrag { start, ..default() };
+    }
+    for event in drag_events.read() {
+        let Ok(handle) = handles.get(event.target) else {
+            continue;
+        };
+        let position = event.pointer_location.position;
+        let (Some(previous), Some(current)) = (
+            camera.viewport_to_world(camera_transform, position - event.delta),
+            camera.viewport_to_world(camera_transform, position),
+        ) else {
+            continue;
+        };
+        let axis = handle.axis.direction(gizmo.rotation);
+        let from: Vec<(Entity, Transform)> = models
+            .iter_many(selection.models())
+            .map(|(entity, transform, _)| (entity, *transform))
+            .collect();
+        let to: Vec<(Entity, Transform)> = match handle.mode {
+            GizmoMode::Translate => {
+                let (Some(start), Some(end)) = (
+                    axis_position(previous, pivot, axis),
+                    axis_position(current, pivot, axis),
+                ) else {
+                    continue;
+                };
+                let distance = drag.step(
+                    end - start,
+                    snapping.then_some(|value| snap.position(value)),
+                );
+                let offset = solid.slide(axis * distance, &from);
+                // What solid mode holds back is tried again on the next step
+                drag.undo(distance - offset.dot(axis));
+                from.iter()
+                    .map(|(entity, transform)| {
+                        (
+                            *entity,
+                            transform.with_translation(transform.translation + offset),
+                        )
+                    })
+                    .collect()
+            }
+            GizmoMode::Rotate => {
+                let (Some(start), Some(end)) = (
+                    previous.intersect_plane(pivot, Vec3::Y),
+                    current.intersect_plane(pivot, Vec3::Y),
+                ) else {
+                    continue;
+                };
+                let start = previous.get_point(start) - pivot;
+                let end = current.get_point(end) - pivot;
+                let angle = drag.step(
+                    start.cross(end).y.atan2(start.dot(end)),
+                    snapping.then_some(|angle| snap.angle(angle)),
+                );
+                let rotation = Quat::from_rotation_y(angle);
+                let to: Vec<(Entity, Transform)> = from
+                    .iter()
+                    .map(|(entity, transform)| {
+                        let mut transform = *transform;
+                        transform.translation = pivot + rotation * (transform.translation - pivot);
+                        transform.rotation = rotation * transform.rotation;
+                        (*entity, transform)
+                    })
+                    .collect();
+                if solid.blocks(&from, &to) {
+                    drag.undo(angle);
+                    continue;
+                }
+                to
+            }
+            GizmoMode::Scale => {
+                let factor = match handle.axis.index() {
+                    Some(_) => {
+                        let (Some(start), Some(end)) = (
+                            axis_position(previous, pivot, axis),
+                            axis_position(current, pivot, axis),
+                        ) else {
+                            continue;
+                        };
+                        // Too close to the pivot for the ratio to mean anything
+                        if start.abs() < gizmo.scale.x * 0.1 {
+                            continue;
+                        }
+                        end / start
+                    }
+                    None => (event.delta.y / -100.).exp(),
+                }
+                .clamp(0.5, 2.);
+                let mut to = Vec::new();
+                for (entity, transform, model) in models.iter_many(selection.models()) {
+                    if metadata.get(&model.0).true_scale {
+                        continue;
+                    }
+                    let mut transform = *transform;
+                    let offset = transform.translation - pivot;
+                    match handle.axis.index() {
+                        Some(index) => {
+                            transform.scale[index] =
+                                (transform.scale[index] * factor).max(MIN_SCALE);
+                            transform.translation += axis * offset.dot(axis) * (factor - 1.);
+                        }
+                        None => {
+                            transform.scale =
+                                (transform.scale * factor).max(Vec3::splat(MIN_SCALE));
+                            transform.translation = pivot + offset * factor;
+                        }
+                    }
+                    to.push((entity, transform));
+                }
+                if solid.blocks(&from, &to) {
+                    continue;
+                }
+                to
+            }
+        };
+        for (entity, transform) in to {
+            if let Ok((_, mut model, _)) = models.get_mut(entity) {
+                *model = transform;
+            }
+        }
+    }
+}
+
+// Keeps the handles on the centre of the selection. Scale handles follow the
+// first selected model's rotation since scaling is along its own axes.
+#[allow(clippy::type_complexity)]
+fn update_gizmo(
+    mode: Res<GizmoMode>,
+    selection: Res<Selection>,
+    models: Query<&Transform, (With<PlacedModel>, Without<TransformGizmo>)>,
+    camera: Query<(&GlobalTransform, &Projection), With<Camera3d>>,
+    mut gizmo: Query<(&mut Transform, &mut Visibility), With<TransformGizmo>>,
+    mut groups: Query<(&ModeHandles, &mut Visibility), Without<TransformGizmo>>,
+) {
+    let (Ok((camera, projection)), Ok((mut transform, mut visibility))) =
+        (camera.get_single(), gizmo.get_single_mut())
+    else {
+        return;
+    };
+    let selected: Vec<&Transform> = models.iter_many(selection.models()).collect();
+    let Some(first) = selected.first() else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+    *visibility = Visibility::Inherited;
+    let pivot =
+        selected.iter().map(|model| model.translation).sum::<Vec3>() / selected.len() as f32;
+    transform.translation = pivot;
+    transform.rotation = match *mode {
+        GizmoMode::Scale => first.rotation,
+        _ => Quat::IDENTITY,
+    };
+    let distance = camera.translation().distance(pivot);
+    transform.scale = Vec3::splat(view_scale(projection, distance) * GIZMO_SCALE);
+    for (handles, mut visibility) in &mut groups {
+        let shown = if handles.0 == *mode {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        if *visibility != shown {
+            *visibility = shown;
+        }
+    }
+}
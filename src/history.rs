@@ -0,0 +1,292 @@
+use bevy::{gltf::Gltf, prelude::*, utils::HashMap};
+use bevy_mod_picking::prelude::*;
+
+use crate::{
+    drag::move_model, selection::Selection, spawn_placed_model, LoadingState, PlacedModel,
+};
+
+pub struct HistoryPlugin;
+
+impl Plugin for HistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<History>()
+            .init_resource::<ActiveGestures>()
+            .add_event::<GestureStartEvent>()
+            .add_event::<GestureEndEvent>()
+            .add_systems(
+                Update,
+                (
+                    record_gesture_start.before(move_model),
+                    record_gesture_end.after(move_model),
+                    undo_redo.run_if(in_state(LoadingState::Loaded)),
+                ),
+            );
+    }
+}
+
+#[derive(Event)]
+pub struct GestureStartEvent(pub Entity);
+
+impl From<ListenerInput<Pointer<DragStart>>> for GestureStartEvent {
+    fn from(value: ListenerInput<Pointer<DragStart>>) -> Self {
+        Self(value.listener())
+    }
+}
+
+#[derive(Event)]
+pub struct GestureEndEvent(pub Entity);
+
+impl From<ListenerInput<Pointer<DragEnd>>> for GestureEndEvent {
+    fn from(value: ListenerInput<Pointer<DragEnd>>) -> Self {
+        Self(value.listener())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ModelSnapshot {
+    pub model: Handle<Gltf>,
+    pub transform: Transform,
+}
+
+#[derive(Debug, Clone)]
+pub enum EditCommand {
+    Spawn {
+        entity: Entity,
+        snapshot: ModelSnapshot,
+    },
+    Delete {
+        entity: Entity,
+        snapshot: ModelSnapshot,
+    },
+    Transform {
+        entity: Entity,
+        before: Transform,
+        after: Transform,
+    },
+    Batch(Vec<EditCommand>),
+}
+
+impl EditCommand {
+    fn describe(&self) -> &'static str {
+        match self {
+            EditCommand::Spawn { .. } => "spawn",
+            EditCommand::Delete { .. } => "delete",
+            EditCommand::Transform { before, after, .. } => {
+                if before.scale != after.scale {
+                    "scale"
+                } else if before.rotation != after.rotation {
+                    "rotate"
+                } else {
+                    "move"
+                }
+            }
+            EditCommand::Batch(commands) => match commands.first() {
+                Some(first)
+                    if commands
+                        .iter()
+                        .all(|command| command.describe() == first.describe()) =>
+                {
+                    first.describe()
+                }
+                _ => "batch edit",
+            },
+        }
+    }
+
+    fn remap(&mut self, old: Entity, new: Entity) {
+        match self {
+            EditCommand::Spawn { entity, .. }
+            | EditCommand::Delete { entity, .. }
+            | EditCommand::Transform { entity, .. } => {
+                if *entity == old {
+                    *entity = new;
+                }
+            }
+            EditCommand::Batch(commands) => {
+                for command in commands {
+                    command.remap(old, new);
+                }
+            }
+        }
+    }
+
+    // Reverts the command, or re-applies it when `redo` is set. Models that
+    // are brought back get a new entity, which is recorded in `respawned`.
+    fn apply(
+        &mut self,
+        redo: bool,
+        commands: &mut Commands,
+        models: &mut Query<&mut Transform, With<PlacedModel>>,
+        gltf_assets: &Assets<Gltf>,
+        respawned: &mut Vec<(Entity, Entity)>,
+    ) {
+        let description = self.describe();
+        let is_spawn = matches!(self, EditCommand::Spawn { .. });
+        match self {
+            EditCommand::Spawn { entity, snapshot } | EditCommand::Delete { entity, snapshot } => {
+                // Undoing a spawn or redoing a delete removes the model, the other two bring it back
+                if is_spawn != redo {
+                    if let Ok(transform) = models.get(*entity) {
+                        snapshot.transform = *transform;
+                    }
+                    commands.entity(*entity).despawn_recursive();
+                } else if let Some(new_entity) = spawn_placed_model(
+                    commands,
+                    gltf_assets,
+                    snapshot.model.clone(),
+                    snapshot.transform,
+                ) {
+                    respawned.push((*entity, new_entity));
+                    *entity = new_entity;
+                }
+            }
+            EditCommand::Transform {
+                entity,
+                before,
+                after,
+            } => {
+                let Ok(mut transform) = models.get_mut(*entity) else {
+                    error!("Unable to {description}: model no longer exists");
+                    return;
+                };
+                *transform = if redo { *after } else { *before };
+            }
+            EditCommand::Batch(batch) => {
+                if redo {
+                    for command in batch.iter_mut() {
+                        command.apply(redo, commands, models, gltf_assets, respawned);
+                    }
+                } else {
+                    for command in batch.iter_mut().rev() {
+                        command.apply(redo, commands, models, gltf_assets, respawned);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Undone spawns and deletes respawn their model as a new entity, so every
+// command referring to the old entity is remapped to the new one.
+#[derive(Resource, Default)]
+pub struct History {
+    undo: Vec<EditCommand>,
+    redo: Vec<EditCommand>,
+}
+
+impl History {
+    pub fn push(&mut self, command: EditCommand) {
+        self.undo.push(command);
+        self.redo.clear();
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    fn remap(&mut self, old: Entity, new: Entity) {
+        for command in self.undo.iter_mut().chain(self.redo.iter_mut()) {
+            command.remap(old, new);
+        }
+    }
+}
+
+// The transforms of every model a gesture moves, keyed by the model grabbed
+#[derive(Resource, Default)]
+pub struct ActiveGestures(HashMap<Entity, Vec<(Entity, Transform)>>);
+
+impl ActiveGestures {
+    // Every model some gesture is moving right now
+    pub fn models(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.0.values().flatten().map(|(entity, _)| *entity)
+    }
+}
+
+pub fn record_gesture_start(
+    mut start_events: EventReader<GestureStartEvent>,
+    models: Query<&Transform, With<PlacedModel>>,
+    selection: Res<Selection>,
+    mut gestures: ResMut<ActiveGestures>,
+) {
+    for GestureStartEvent(entity) in start_events.read() {
+        gestures.0.entry(*entity).or_insert_with(|| {
+            selection
+                .group(*entity)
+                .into_iter()
+                .filter_map(|model| Some((model, *models.get(model).ok()?)))
+                .collect()
+        });
+    }
+}
+
+pub fn record_gesture_end(
+    mut end_events: EventReader<GestureEndEvent>,
+    models: Query<&Transform, With<PlacedModel>>,
+    mut gestures: ResMut<ActiveGestures>,
+    mut history: ResMut<History>,
+) {
+    for GestureEndEvent(entity) in end_events.read() {
+        let Some(group) = gestures.0.remove(entity) else {
+            continue;
+        };
+        let mut changed: Vec<EditCommand> = group
+            .into_iter()
+            .filter_map(|(entity, before)| {
+                let after = *models.get(entity).ok()?;
+                (before != after).then_some(EditCommand::Transform {
+                    entity,
+                    before,
+                    after,
+                })
+            })
+            .collect();
+        match changed.len() {
+            0 => {}
+            1 => history.push(changed.remove(0)),
+            _ => history.push(EditCommand::Batch(changed)),
+        }
+    }
+}
+
+fn undo_redo(
+    keys: Res<Input<KeyCode>>,
+    mut commands: Commands,
+    mut history: ResMut<History>,
+    mut models: Query<&mut Transform, With<PlacedModel>>,
+    gltf_assets: Res<Assets<Gltf>>,
+) {
+    if !keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight])
+        || !keys.just_pressed(KeyCode::Z)
+    {
+        return;
+    }
+    let redo = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let command = if redo {
+        history.redo.pop()
+    } else {
+        history.undo.pop()
+    };
+    let Some(mut command) = command else {
+        return;
+    };
+    let description = command.describe();
+    let mut respawned = Vec::new();
+    command.apply(
+        redo,
+        &mut commands,
+        &mut models,
+        &gltf_assets,
+        &mut respawned,
+    );
+    for (old, new) in respawned {
+        history.remap(old, new);
+    }
+    if redo {
+        info!("Redid {description}");
+        history.undo.push(command);
+    } else {
+        info!("Undid {description}");
+        history.redo.push(command);
+    }
+}
@@ -0,0 +1,475 @@
+use std::{
+    fmt,
+    io::Cursor,
+    sync::{Arc, Mutex},
+};
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    gltf::Gltf,
+    prelude::*,
+    render::{mesh::Indices, render_resource::PrimitiveTopology},
+    utils::{BoxedFuture, HashMap},
+};
+use ply_rs::ply::{DefaultElement, Property};
+
+// Loaders for the formats phone and photogrammetry scanners export. Each one
+// produces a `Gltf` with a single scene so imported scans go through the same
+// model list and placement code as glTF files.
+pub struct ImportPlugin;
+
+impl Plugin for ImportPlugin {
+    fn build(&self, app: &mut App) {
+        let errors = ImportErrors::default();
+        app.insert_resource(errors.clone())
+            .register_asset_loader(ObjLoader {
+                errors: errors.clone(),
+            })
+            .register_asset_loader(PlyLoader {
+                errors: errors.clone(),
+            })
+            .register_asset_loader(StlLoader { errors });
+    }
+}
+
+// Why the last load of each scan failed, by asset path. Bevy only logs loader
+// errors, so the loaders keep them for the load report.
+#[derive(Resource, Clone, Default)]
+pub struct ImportErrors(Arc<Mutex<HashMap<String, String>>>);
+
+impl ImportErrors {
+    pub fn take(&self, path: &str) -> Option<String> {
+        self.0.lock().ok()?.remove(path)
+    }
+
+    fn record<T>(&self, path: String, result: Result<T, ImportError>) -> Result<T, ImportError> {
+        if let Ok(mut errors) = self.0.lock() {
+            match &result {
+                Ok(_) => errors.remove(&path),
+                Err(err) => errors.insert(path, err.to_string()),
+            };
+        }
+        result
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(std::io::Error),
+    Obj(tobj::LoadError),
+    MissingElement(&'static str),
+    Empty,
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Io(err) => write!(f, "unable to read model: {err}"),
+            ImportError::Obj(err) => write!(f, "invalid OBJ file: {err}"),
+            ImportError::MissingElement(element) => write!(f, "PLY file has no {element} element"),
+            ImportError::Empty => write!(f, "model has no triangles"),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<std::io::Error> for ImportError {
+    fn from(err: std::io::Error) -> Self {
+        ImportError::Io(err)
+    }
+}
+
+async fn read_bytes(reader: &mut Reader<'_>) -> Result<Vec<u8>, ImportError> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes).await?;
+    Ok(bytes)
+}
+
+// Scans often have inconsistent winding, so both sides are drawn
+fn scan_material(base_color: Color) -> StandardMaterial {
+    StandardMaterial {
+        base_color,
+        perceptual_roughness: 0.9,
+        double_sided: true,
+        cull_mode: None,
+        ..default()
+    }
+}
+
+fn triangle_mesh(
+    positions: Vec<[f32; 3]>,
+    normals: Option<Vec<[f32; 3]>>,
+    uvs: Option<Vec<[f32; 2]>>,
+    colors: Option<Vec<[f32; 4]>>,
+    indices: Vec<u32>,
+) -> Mesh {
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    if let Some(uvs) = uvs {
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    }
+    if let Some(colors) = colors {
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    }
+    mesh.set_indices(Some(Indices::U32(indices)));
+    match normals {
+        Some(normals) => mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals),
+        None => {
+            mesh.duplicate_vertices();
+            mesh.compute_flat_normals();
+        }
+    }
+    mesh
+}
+
+// Wraps the meshes in a scene and a `Gltf` using the same labels the glTF
+// loader does
+fn scanned_model(
+    load_context: &mut LoadContext,
+    parts: Vec<(Mesh, StandardMaterial)>,
+) -> Result<Gltf, ImportError> {
+    if parts.is_empty() {
+        return Err(ImportError::Empty);
+    }
+    let mut world = World::default();
+    let mut materials = Vec::new();
+    world
+        .spawn(SpatialBundle::INHERITED_IDENTITY)
+        .with_children(|parent| {
+            for (index, (mesh, material)) in parts.into_iter().enumerate() {
+                let mesh = load_context.add_labeled_asset(format!("Mesh{index}"), mesh);
+                let material = load_context.add_labeled_asset(format!("Material{index}"), material);
+                materials.push(material.clone());
+                parent.spawn(PbrBundle {
+                    mesh,
+                    material,
+                    ..default()
+                });
+            }
+        });
+    let scene = load_context.add_labeled_asset("Scene0".to_string(), Scene::new(world));
+    Ok(Gltf {
+        scenes: vec![scene.clone()],
+        named_scenes: HashMap::default(),
+        meshes: Vec::new(),
+        named_meshes: HashMap::default(),
+        materials,
+        named_materials: HashMap::default(),
+        nodes: Vec::new(),
+        named_nodes: HashMap::default(),
+        default_scene: Some(scene),
+        animations: Vec::new(),
+        named_animations: HashMap::default(),
+    })
+}
+
+struct ObjLoader {
+    errors: ImportErrors,
+}
+
+impl AssetLoader for ObjLoader {
+    type Asset = Gltf;
+    type Settings = ();
+    type Error = ImportError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Gltf, ImportError>> {
+        Box::pin(async move {
+            let path = load_context.asset_path().to_string();
+            let result: Result<Gltf, ImportError> = async {
+                let bytes = read_bytes(reader).await?;
+                // tobj asks for material libraries synchronously while parsing, so
+                // they are read up front
+                let mut libraries = HashMap::new();
+                for line in String::from_utf8_lossy(&bytes).lines() {
+                    let Some(("mtllib", name)) = line.trim().split_once(' ') else {
+                        continue;
+                    };
+                    let name = name.trim().to_string();
+                    let Ok(path) = load_context.asset_path().resolve_embed(&name) else {
+                        continue;
+                    };
+                    match load_context.read_asset_bytes(path).await {
+                        Ok(library) => {
+                            libraries.insert(name, library);
+                        }
+                        Err(err) => warn!("Unable to read material library {name}: {err}"),
+                    }
+                }
+                let options = tobj::LoadOptions {
+                    single_index: true,
+                    triangulate: true,
+                    ignore_points: true,
+                    ignore_lines: true,
+                };
+                let (models, materials) =
+                    tobj::load_obj_buf(&mut Cursor::new(bytes), &options, |path| {
+                        let library = libraries
+                            .get(path.to_string_lossy().as_ref())
+                            .ok_or(tobj::LoadError::OpenFileFailed)?;
+                        tobj::load_mtl_buf(&mut Cursor::new(library))
+                    })
+                    .map_err(ImportError::Obj)?;
+                let materials = materials.unwrap_or_else(|err| {
+                    warn!("Unable to load OBJ materials: {err}");
+                    Vec::new()
+                });
+                let materials: Vec<StandardMaterial> = materials
+                    .into_iter()
+                    .map(|material| {
+                        let [r, g, b] = material.diffuse.unwrap_or([1., 1., 1.]);
+                        let mut standard =
+                            scan_material(Color::rgba(r, g, b, material.dissolve.unwrap_or(1.)));
+                        if let Some(texture) = material.diffuse_texture {
+                            match load_context
+                                .asset_path()
+                                .resolve_embed(&texture.replace('\\', "/"))
+                            {
+                                Ok(path) => {
+                                    standard.base_color_texture = Some(load_context.load(path))
+                                }
+                                Err(err) => warn!("Invalid texture path {texture}: {err}"),
+                            }
+                        }
+                        standard
+                    })
+                    .collect();
+                let parts = models
+                    .into_iter()
+                    .filter(|model| !model.mesh.indices.is_empty())
+                    .map(|model| {
+                        let mesh = model.mesh;
+                        let material = mesh
+                            .material_id
+                            .and_then(|id| materials.get(id).cloned())
+                            .unwrap_or_else(|| scan_material(Color::WHITE));
+                        let positions = mesh
+                            .positions
+                            .chunks(3)
+                            .map(|p| [p[0], p[1], p[2]])
+                            .collect();
+                        let normals = (!mesh.normals.is_empty())
+                            .then(|| mesh.normals.chunks(3).map(|n| [n[0], n[1], n[2]]).collect());
+                        // OBJ texture coordinates start at the bottom of the image
+                        let uvs = (!mesh.texcoords.is_empty()).then(|| {
+                            mesh.texcoords
+                                .chunks(2)
+                                .map(|t| [t[0], 1. - t[1]])
+                                .collect()
+                        });
+                        let colors = (!mesh.vertex_color.is_empty()).then(|| {
+                            mesh.vertex_color
+                                .chunks(3)
+                                .map(|c| [c[0], c[1], c[2], 1.])
+                                .collect()
+                        });
+                        (
+                            triangle_mesh(positions, normals, uvs, colors, mesh.indices),
+                            material,
+                        )
+                    })
+                    .collect();
+                scanned_model(load_context, parts)
+            }
+            .await;
+            self.errors.record(path, result)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["obj"]
+    }
+}
+
+struct PlyLoader {
+    errors: ImportErrors,
+}
+
+fn ply_scalar(element: &DefaultElement, key: &str) -> Option<f32> {
+    match element.get(key)? {
+        Property::Char(value) => Some(*value as f32),
+        Property::UChar(value) => Some(*value as f32),
+        Property::Short(value) => Some(*value as f32),
+        Property::UShort(value) => Some(*value as f32),
+        Property::Int(value) => Some(*value as f32),
+        Property::UInt(value) => Some(*value as f32),
+        Property::Float(value) => Some(*value),
+        Property::Double(value) => Some(*value as f32),
+        _ => None,
+    }
+}
+
+// Colour channels are either bytes or already in 0..1
+fn ply_channel(element: &DefaultElement, key: &str) -> Option<f32> {
+    match element.get(key)? {
+        Property::UChar(value) => Some(*value as f32 / 255.),
+        _ => ply_scalar(element, key),
+    }
+}
+
+fn ply_indices(element: &DefaultElement) -> Option<Vec<u32>> {
+    let list = element
+        .get("vertex_indices")
+        .or_else(|| element.get("vertex_index"))?;
+    Some(match list {
+        Property::ListChar(list) => list.iter().map(|&i| i as u32).collect(),
+        Property::ListUChar(list) => list.iter().map(|&i| i as u32).collect(),
+        Property::ListShort(list) => list.iter().map(|&i| i as u32).collect(),
+        Property::ListUShort(list) => list.iter().map(|&i| i as u32).collect(),
+        Property::ListInt(list) => list.iter().map(|&i| i as u32).collect(),
+        Property::ListUInt(list) => list.clone(),
+        _ => return None,
+    })
+}
+
+impl AssetLoader for PlyLoader {
+    type Asset = Gltf;
+    type Settings = ();
+    type Error = ImportError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Gltf, ImportError>> {
+        Box::pin(async move {
+            let path = load_context.asset_path().to_string();
+            let result: Result<Gltf, ImportError> = async {
+                let bytes = read_bytes(reader).await?;
+                let ply = ply_rs::parser::Parser::<DefaultElement>::new()
+                    .read_ply(&mut Cursor::new(bytes))?;
+                let vertices = ply
+                    .payload
+                    .get("vertex")
+                    .ok_or(ImportError::MissingElement("vertex"))?;
+                let faces = ply
+                    .payload
+                    .get("face")
+                    .ok_or(ImportError::MissingElement("face"))?;
+                let positions = vertices
+                    .iter()
+                    .map(|v| ["x", "y", "z"].map(|key| ply_scalar(v, key).unwrap_or_default()))
+                    .collect();
+                let normals = vertices
+                    .iter()
+                    .map(|v| {
+                        Some([
+                            ply_scalar(v, "nx")?,
+                            ply_scalar(v, "ny")?,
+                            ply_scalar(v, "nz")?,
+                        ])
+                    })
+                    .collect();
+                let uvs = vertices
+                    .iter()
+                    .map(|v| {
+                        let u = ply_scalar(v, "s").or_else(|| ply_scalar(v, "u"))?;
+                        let v = ply_scalar(v, "t").or_else(|| ply_scalar(v, "v"))?;
+                        Some([u, 1. - v])
+                    })
+                    .collect();
+                let colors = vertices
+                    .iter()
+                    .map(|v| {
+                        Some([
+                            ply_channel(v, "red")?,
+                            ply_channel(v, "green")?,
+                            ply_channel(v, "blue")?,
+                            ply_channel(v, "alpha").unwrap_or(1.),
+                        ])
+                    })
+                    .collect();
+                let vertex_count = vertices.len() as u32;
+                let mut indices = Vec::new();
+                for face in faces {
+                    let Some(polygon) = ply_indices(face) else {
+                        continue;
+                    };
+                    if polygon.iter().any(|&i| i >= vertex_count) {
+                        continue;
+                    }
+                    // Faces are convex polygons, split into a fan
+                    for i in 1..polygon.len().saturating_sub(1) {
+                        indices.extend([polygon[0], polygon[i], polygon[i + 1]]);
+                    }
+                }
+                if indices.is_empty() {
+                    return Err(ImportError::Empty);
+                }
+                let mesh = triangle_mesh(positions, normals, uvs, colors, indices);
+                scanned_model(load_context, vec![(mesh, scan_material(Color::WHITE))])
+            }
+            .await;
+            self.errors.record(path, result)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ply"]
+    }
+}
+
+struct StlLoader {
+    errors: ImportErrors,
+}
+
+impl AssetLoader for StlLoader {
+    type Asset = Gltf;
+    type Settings = ();
+    type Error = ImportError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Gltf, ImportError>> {
+        Box::pin(async move {
+            let path = load_context.asset_path().to_string();
+            let result: Result<Gltf, ImportError> = async {
+                let bytes = read_bytes(reader).await?;
+                let stl = stl_io::read_stl(&mut Cursor::new(bytes))?;
+                let mut positions = Vec::new();
+                let mut normals = Vec::new();
+                for face in &stl.faces {
+                    let corners = face.vertices.map(|i| {
+                        let vertex = &stl.vertices[i];
+                        Vec3::new(vertex[0], vertex[1], vertex[2])
+                    });
+                    // Some exporters leave the facet normals zeroed
+                    let normal = Vec3::new(face.normal[0], face.normal[1], face.normal[2])
+                        .try_normalize()
+                        .unwrap_or_else(|| {
+                            (corners[1] - corners[0])
+                                .cross(corners[2] - corners[0])
+                                .normalize_or_zero()
+                        });
+                    positions.extend(corners.map(|corner| corner.to_array()));
+                    normals.extend([normal.to_array(); 3]);
+                }
+                if positions.is_empty() {
+                    return Err(ImportError::Empty);
+                }
+                let indices = (0..positions.len() as u32).collect();
+                let mesh = triangle_mesh(positions, Some(normals), None, None, indices);
+                // STL has no colour, so scans get a neutral grey
+                scanned_model(
+                    load_context,
+                    vec![(mesh, scan_material(Color::rgb(0.8, 0.8, 0.8)))],
+                )
+            }
+            .await;
+            self.errors.record(path, result)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["stl"]
+    }
+}
@@ -0,0 +1,403 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::{gltf::Gltf, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bookmarks::{Bookmark, Bookmarks, WalkthroughKey},
+    history::History,
+    pivot::LegacyOrigin,
+    room::Room,
+    spawn_placed_model, LoadedModelList, LoadingState, PlacedModel,
+};
+
+// Bump this whenever the shape of `LayoutFile` changes and add a migration
+// for the previous version to `LayoutFile::from_ron`.
+pub const LAYOUT_VERSION: u32 = 5;
+
+pub struct LayoutPlugin;
+
+impl Plugin for LayoutPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LayoutPath>()
+            .init_resource::<LayoutLoadReport>()
+            .add_event::<SaveLayoutEvent>()
+            .add_event::<LoadLayoutEvent>()
+            .add_systems(
+                Update,
+                (layout_shortcuts, save_layout, load_layout).run_if(in_state(LoadingState::Loaded)),
+            );
+    }
+}
+
+#[derive(Debug, Resource)]
+pub struct LayoutPath(pub PathBuf);
+
+impl Default for LayoutPath {
+    fn default() -> Self {
+        Self(PathBuf::from("layout.ron"))
+    }
+}
+
+#[derive(Debug, Event)]
+pub struct SaveLayoutEvent(pub PathBuf);
+
+#[derive(Debug, Event)]
+pub struct LoadLayoutEvent(pub PathBuf);
+
+// Model paths referenced by the last loaded layout that are not in `LoadedModelList`
+#[derive(Debug, Resource, Default)]
+pub struct LayoutLoadReport {
+    pub missing: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LayoutFile {
+    pub version: u32,
+    pub room: Room,
+    pub models: Vec<ModelRecord>,
+    #[serde(default)]
+    pub bookmarks: Vec<Bookmark>,
+    #[serde(default)]
+    pub walkthrough: Vec<WalkthroughKey>,
+}
+
+// Version 1 only stored the size of a rectangular room
+#[derive(Deserialize)]
+struct LayoutFileV1 {
+    room: RoomSizeV1,
+    models: Vec<ModelRecord>,
+}
+
+#[derive(Deserialize)]
+struct RoomSizeV1 {
+    width: f32,
+    length: f32,
+    height: f32,
+}
+
+impl From<LayoutFileV1> for LayoutFile {
+    fn from(layout: LayoutFileV1) -> Self {
+        Self {
+            version: LAYOUT_VERSION,
+            room: Room::rectangle(layout.room.width, layout.room.length, layout.room.height),
+            models: layout.models,
+            bookmarks: Vec::new(),
+            walkthrough: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelRecord {
+    pub path: String,
+    pub transform: TransformRecord,
+    // Set for records from before version 4, whose transforms are relative
+    // to the model file's own origin rather than its bottom centre
+    #[serde(skip)]
+    pub legacy_origin: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TransformRecord {
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+}
+
+impl From<&Transform> for TransformRecord {
+    fn from(transform: &Transform) -> Self {
+        Self {
+            translation: transform.translation.to_array(),
+            rotation: transform.rotation.to_array(),
+            scale: transform.scale.to_array(),
+        }
+    }
+}
+
+impl From<TransformRecord> for Transform {
+    fn from(record: TransformRecord) -> Self {
+        Self {
+            translation: Vec3::from_array(record.translation),
+            rotation: Quat::from_array(record.rotation).normalize(),
+            scale: Vec3::from_array(record.scale),
+        }
+    }
+}
+
+impl ModelRecord {
+    pub fn new(model: &PlacedModel, transform: &Transform) -> Option<Self> {
+        Some(Self {
+            path: model.0.path()?.to_string(),
+            transform: transform.into(),
+            legacy_origin: false,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum LayoutError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    Serialize(ron::Error),
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayoutError::Io(err) => write!(f, "{err}"),
+            LayoutError::Parse(err) => write!(f, "invalid layout file: {err}"),
+            LayoutError::Serialize(err) => write!(f, "unable to serialize layout: {err}"),
+            LayoutError::UnsupportedVersion(version) => write!(
+                f,
+                "layout version {version} is not supported (newest supported is {LAYOUT_VERSION})"
+            ),
+        }
+    }
+}
+
+// Only the version is read first so that the rest of the file can be parsed
+// according to the schema it was written with.
+#[derive(Deserialize)]
+struct LayoutHeader {
+    version: u32,
+}
+
+impl LayoutFile {
+    pub fn from_ron(text: &str) -> Result<Self, LayoutError> {
+        let header: LayoutHeader = ron::from_str(text).map_err(LayoutError::Parse)?;
+        let mut layout: LayoutFile = match header.version {
+            1 => ron::from_str::<LayoutFileV1>(text)
+                .map(LayoutFile::from)
+                .map_err(LayoutError::Parse)?,
+            // Version 2 only lacks room openings, which default to none,
+            // version 3 only differs in where model origins are and version 4
+            // only lacks camera bookmarks
+            2..=4 | LAYOUT_VERSION => ron::from_str(text).map_err(LayoutError::Parse)?,
+            version => return Err(LayoutError::UnsupportedVersion(version)),
+        };
+        if header.version < 4 {
+            for model in &mut layout.models {
+                model.legacy_origin = true;
+            }
+        }
+        layout.version = LAYOUT_VERSION;
+        Ok(layout)
+    }
+
+    pub fn to_ron(&self) -> Result<String, LayoutError> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(LayoutError::Serialize)
+    }
+
+    pub fn read(path: &Path) -> Result<Self, LayoutError> {
+        let text = fs::read_to_string(path).map_err(LayoutError::Io)?;
+        Self::from_ron(&text)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), LayoutError> {
+        fs::write(path, self.to_ron()?).map_err(LayoutError::Io)
+    }
+}
+
+fn layout_shortcuts(
+    keys: Res<Input<KeyCode>>,
+    layout_path: Res<LayoutPath>,
+    mut save_events: EventWriter<SaveLayoutEvent>,
+    mut load_events: EventWriter<LoadLayoutEvent>,
+) {
+    if !keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+    if keys.just_pressed(KeyCode::S) {
+        save_events.send(SaveLayoutEvent(layout_path.0.clone()));
+    }
+    if keys.just_pressed(KeyCode::O) {
+        load_events.send(LoadLayoutEvent(layout_path.0.clone()));
+    }
+}
+
+fn save_layout(
+    mut save_events: EventReader<SaveLayoutEvent>,
+    models: Query<(&PlacedModel, &Transform)>,
+    room: Res<Room>,
+    bookmarks: Res<Bookmarks>,
+) {
+    for SaveLayoutEvent(path) in save_events.read() {
+        let layout = LayoutFile {
+            version: LAYOUT_VERSION,
+            room: room.clone(),
+            models: models
+                .iter()
+                .filter_map(|(model, transform)| ModelRecord::new(model, transform))
+                .collect(),
+            bookmarks: bookmarks.bookmarks.clone(),
+            walkthrough: bookmarks.walkthrough.clone(),
+        };
+        match layout.write(path) {
+            Ok(()) => info!("Saved layout to {}", path.display()),
+            Err(err) => error!("Unable to save layout to {}: {err}", path.display()),
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn load_layout(
+    mut commands: Commands,
+    mut load_events: EventReader<LoadLayoutEvent>,
+    placed_models: Query<Entity, With<PlacedModel>>,
+    model_list: Res<LoadedModelList>,
+    gltf_assets: Res<Assets<Gltf>>,
+    mut room: ResMut<Room>,
+    mut bookmarks: ResMut<Bookmarks>,
+    mut report: ResMut<LayoutLoadReport>,
+    mut history: ResMut<History>,
+) {
+    let Some(LoadLayoutEvent(path)) = load_events.read().last() else {
+        return;
+    };
+    let layout = match LayoutFile::read(path) {
+        Ok(layout) => layout,
+        Err(err) => {
+            error!("Unable to load layout from {}: {err}", path.display());
+            return;
+        }
+    };
+    for entity in &placed_models {
+        commands.entity(entity).despawn_recursive();
+    }
+    history.clear();
+    if *room != layout.room {
+        *room = layout.room;
+    }
+    bookmarks.bookmarks = layout.bookmarks;
+    bookmarks.walkthrough = layout.walkthrough;
+    report.missing.clear();
+    for record in layout.models {
+        let spawned = model_list.find_by_path(&record.path).and_then(|handle| {
+            spawn_placed_model(
+                &mut commands,
+                &gltf_assets,
+                handle.clone(),
+                record.transform.into(),
+            )
+        });
+        match spawned {
+            Some(entity) if record.legacy_origin => {
+                commands.entity(entity).insert(LegacyOrigin);
+            }
+            Some(_) => {}
+            None => report.missing.push(record.path),
+        }
+    }
+    if report.missing.is_empty() {
+        info!("Loaded layout from {}", path.display());
+    } else {
+        warn!(
+            "Loaded layout from {} but these models are missing: {}",
+            path.display(),
+            report.missing.join(", ")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHAIR: &str = r#"(
+        path: "models/chair.glb",
+        transform: (
+            translation: (1.0, 0.0, 2.0),
+            rotation: (0.0, 0.0, 0.0, 1.0),
+            scale: (1.0, 1.0, 1.0),
+        ),
+    )"#;
+
+    fn room_v2() -> String {
+        r#"(
+            outline: [(-2.0, -2.5), (-2.0, 2.5), (2.0, 2.5), (2.0, -2.5)],
+            wall_height: 2.5,
+            floor_material: (texture: None, color: (1.0, 1.0, 1.0, 1.0)),
+            wall_materials: [(texture: None, color: (1.0, 1.0, 1.0, 1.0))],
+        )"#
+        .to_string()
+    }
+
+    fn layout(version: u32, room: &str) -> String {
+        format!("(version: {version}, room: {room}, models: [{CHAIR}])")
+    }
+
+    #[test]
+    fn migrates_version_1_room_size() {
+        let text = layout(1, "(width: 4.0, length: 5.0, height: 2.5)");
+        let layout = LayoutFile::from_ron(&text).unwrap();
+        assert_eq!(layout.version, LAYOUT_VERSION);
+        assert_eq!(layout.room, Room::rectangle(4., 5., 2.5));
+        assert_eq!(layout.models.len(), 1);
+        assert!(layout.models[0].legacy_origin);
+        assert!(layout.bookmarks.is_empty());
+    }
+
+    #[test]
+    fn migrates_versions_2_to_4() {
+        for version in 2..=4 {
+            let layout = LayoutFile::from_ron(&layout(version, &room_v2())).unwrap();
+            assert_eq!(layout.version, LAYOUT_VERSION);
+            assert_eq!(layout.room.corners().len(), 4);
+            assert!(layout.room.openings.is_empty());
+            assert_eq!(layout.models[0].path, "models/chair.glb");
+            // Model origins moved to the bottom centre in version 4
+            assert_eq!(layout.models[0].legacy_origin, version < 4);
+            assert!(layout.walkthrough.is_empty());
+        }
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let text = layout(LAYOUT_VERSION + 1, &room_v2());
+        assert!(matches!(
+            LayoutFile::from_ron(&text),
+            Err(LayoutError::UnsupportedVersion(version)) if version == LAYOUT_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn round_trips() {
+        let transform = Transform::from_xyz(1., 0., -2.).with_scale(Vec3::splat(1.5));
+        let mut room = Room::rectangle(3., 6., 2.4);
+        room.openings
+            .push(crate::openings::WallOpening::door(0, 1.5));
+        let layout = LayoutFile {
+            version: LAYOUT_VERSION,
+            room,
+            models: vec![ModelRecord {
+                path: "models/table.glb".to_string(),
+                transform: (&transform).into(),
+                legacy_origin: false,
+            }],
+            bookmarks: vec![Bookmark {
+                name: "Door".to_string(),
+                key: Some(1),
+                transform: (&transform).into(),
+            }],
+            walkthrough: vec![WalkthroughKey {
+                bookmark: "Door".to_string(),
+                seconds: 2.,
+            }],
+        };
+        let loaded = LayoutFile::from_ron(&layout.to_ron().unwrap()).unwrap();
+        assert_eq!(loaded.room, layout.room);
+        assert_eq!(loaded.models[0].path, "models/table.glb");
+        assert!(!loaded.models[0].legacy_origin);
+        assert_eq!(Transform::from(loaded.models[0].transform), transform);
+        assert_eq!(loaded.bookmarks[0].name, "Door");
+        assert_eq!(loaded.bookmarks[0].key, Some(1));
+        assert_eq!(loaded.walkthrough[0].bookmark, "Door");
+        assert_eq!(loaded.walkthrough[0].seconds, 2.);
+    }
+}
@@ -0,0 +1,229 @@
+use std::{
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+use bevy::{asset::io::AssetSourceBuilder, prelude::*};
+
+use crate::{
+    loading::{asset_dir, find_models, is_model, ModelLoads, MODEL_EXTENSIONS},
+    metadata::metadata_file,
+};
+
+pub const LIBRARY_SOURCE: &str = "library";
+
+// Models imported at runtime are copied into a per-user library, which is
+// served to the asset server as `library://`. This has to be added before
+// `DefaultPlugins` since asset sources are fixed once `AssetPlugin` is built.
+pub struct LibraryPlugin;
+
+impl Plugin for LibraryPlugin {
+    fn build(&self, app: &mut App) {
+        let dir = library_dir();
+        app.register_asset_source(
+            LIBRARY_SOURCE,
+            AssetSourceBuilder::platform_default(&dir.to_string_lossy(), None),
+        )
+        .insert_resource(ModelLibrary { dir })
+        .add_systems(Startup, (load_library, import_arguments))
+        .add_systems(Update, import_dropped_files);
+    }
+}
+
+#[derive(Resource)]
+pub struct ModelLibrary {
+    dir: PathBuf,
+}
+
+fn library_dir() -> PathBuf {
+    directories::ProjectDirs::from("", "", "Deco")
+        .map(|dirs| dirs.data_dir().join("models"))
+        .unwrap_or_else(|| PathBuf::from("library"))
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+// Texture and material paths an MTL file refers to
+fn mtl_references(path: &Path) -> Vec<String> {
+    let Ok(text) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    text.lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            let (keyword, _) = line.split_once(' ')?;
+            let is_map =
+                keyword.starts_with("map_") || ["bump", "disp", "decal", "norm"].contains(&keyword);
+            // Options come before the file name, which is last
+            is_map.then(|| {
+                line.split_whitespace()
+                    .last()
+                    .unwrap_or_default()
+                    .to_string()
+            })
+        })
+        .collect()
+}
+
+// Files an OBJ or glTF loads by relative path, which have to be copied with it
+fn companion_files(path: &Path) -> Vec<String> {
+    let Ok(text) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    let dir = path.parent().unwrap_or(Path::new(""));
+    match extension(path).as_str() {
+        "obj" => text
+            .lines()
+            .filter_map(|line| match line.trim().split_once(' ') {
+                Some(("mtllib", name)) => Some(name.trim().to_string()),
+                _ => None,
+            })
+            .flat_map(|library| {
+                let mut files = mtl_references(&dir.join(&library));
+                files.push(library);
+                files
+            })
+            .collect(),
+        "gltf" => text
+            .split("\"uri\"")
+            .skip(1)
+            .filter_map(|rest| rest.split('"').nth(1))
+            .filter(|uri| !uri.starts_with("data:"))
+            .map(|uri| uri.replace("%20", " "))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+impl ModelLibrary {
+    // Where the file behind a model's asset path is on disk
+    pub fn file_path(&self, asset_path: &str) -> PathBuf {
+        match asset_path.strip_prefix(&format!("{LIBRARY_SOURCE}://")) {
+            Some(relative) => self.dir.join(relative),
+            None => asset_dir().join(asset_path),
+        }
+    }
+
+    // Paths of the library's models relative to the library
+    pub fn models(&self) -> Vec<String> {
+        let mut models = Vec::new();
+        find_models(&self.dir, &self.dir, &mut models);
+        models
+    }
+
+    // Copies a model and its companion files into their own folder in the
+    // library, returning the model's path within the library. Importing the
+    // same file again reuses the earlier copy.
+    fn copy_in(&self, source: &Path) -> io::Result<PathBuf> {
+        let file_name = source
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+        let stem = source
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let contents = fs::read(source)?;
+        let mut folder = PathBuf::from(&stem);
+        for copy in 2.. {
+            let existing = self.dir.join(&folder).join(file_name);
+            if !existing.exists() {
+                break;
+            }
+            if fs::read(&existing)? == contents {
+                return Ok(folder.join(file_name));
+            }
+            folder = PathBuf::from(format!("{stem}-{copy}"));
+        }
+        let destination = self.dir.join(&folder);
+        fs::create_dir_all(&destination)?;
+        fs::write(destination.join(file_name), contents)?;
+        // Keep any scale settings that came with the model
+        let metadata = metadata_file(source);
+        if metadata.exists() {
+            fs::copy(&metadata, metadata_file(&destination.join(file_name)))?;
+        }
+        let source_dir = source.parent().unwrap_or(Path::new(""));
+        for companion in companion_files(source) {
+            let relative = PathBuf::from(companion.replace('\\', "/"));
+            if relative
+                .components()
+                .any(|component| !matches!(component, Component::Normal(_)))
+            {
+                warn!(
+                    "Not copying {}, it is outside the model's folder",
+                    relative.display()
+                );
+                continue;
+            }
+            let target = destination.join(&relative);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            if let Err(err) = fs::copy(source_dir.join(&relative), &target) {
+                warn!("Unable to copy {}: {err}", relative.display());
+            }
+        }
+        Ok(folder.join(file_name))
+    }
+}
+
+pub fn library_path(relative: &str) -> String {
+    format!("{LIBRARY_SOURCE}://{relative}")
+}
+
+fn import(path: &Path, library: &ModelLibrary, asset_server: &AssetServer, loads: &mut ModelLoads) {
+    if !is_model(path) {
+        warn!(
+            "Unable to import {}: supported formats are {}",
+            path.display(),
+            MODEL_EXTENSIONS.join(", ")
+        );
+        return;
+    }
+    match library.copy_in(path) {
+        Ok(relative) => {
+            info!("Imported {} into the model library", path.display());
+            let relative = relative.to_string_lossy().replace('\\', "/");
+            loads.start(asset_server, library_path(&relative));
+        }
+        Err(err) => error!("Unable to import {}: {err}", path.display()),
+    }
+}
+
+// Models imported in earlier sessions
+fn load_library(
+    library: Res<ModelLibrary>,
+    asset_server: Res<AssetServer>,
+    mut loads: ResMut<ModelLoads>,
+) {
+    for relative in library.models() {
+        loads.start(&asset_server, library_path(&relative));
+    }
+}
+
+fn import_arguments(
+    library: Res<ModelLibrary>,
+    asset_server: Res<AssetServer>,
+    mut loads: ResMut<ModelLoads>,
+) {
+    for argument in std::env::args_os().skip(1) {
+        import(Path::new(&argument), &library, &asset_server, &mut loads);
+    }
+}
+
+fn import_dropped_files(
+    mut drop_events: EventReader<FileDragAndDrop>,
+    library: Res<ModelLibrary>,
+    asset_server: Res<AssetServer>,
+    mut loads: ResMut<ModelLoads>,
+) {
+    for event in drop_events.read() {
+        if let FileDragAndDrop::DroppedFile { path_buf, .. } = event {
+            import(path_buf, &library, &asset_server, &mut loads);
+        }
+    }
+}
@@ -0,0 +1,297 @@
+use std::path::{Path, PathBuf};
+
+use bevy::{
+    asset::{io::file::FileAssetReader, LoadState},
+    gltf::Gltf,
+    prelude::*,
+};
+use bevy_mod_picking::prelude::*;
+
+use crate::{
+    import::ImportErrors, reload::MissingModel, spawn_model, LoadedModelList, ModelListParent,
+    PlacedModel, HOVERED_BUTTON, NORMAL_BUTTON,
+};
+
+pub const MODEL_DIR: &str = "models";
+pub const MODEL_EXTENSIONS: [&str; 5] = ["glb", "gltf", "obj", "ply", "stl"];
+
+// Models are loaded file by file rather than with `load_folder`, which fails
+// as a whole when any one file does. Failed files are collected into a
+// `LoadReport` and shown in a panel instead.
+pub struct LoadingPlugin;
+
+impl Plugin for LoadingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ModelLoads>()
+            .init_resource::<LoadReport>()
+            .add_systems(Startup, spawn_error_panel)
+            .add_systems(
+                Update,
+                (
+                    track_model_loads,
+                    update_error_panel.run_if(resource_changed::<LoadReport>()),
+                    dismiss_error_panel,
+                )
+                    .chain(),
+            );
+    }
+}
+
+pub fn is_model(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| MODEL_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+}
+
+pub fn asset_dir() -> PathBuf {
+    FileAssetReader::get_base_path().join("assets")
+}
+
+// Asset paths of every model file under `dir`, relative to `root`
+pub fn find_models(root: &Path, dir: &Path, found: &mut Vec<String>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            find_models(root, &path, found);
+        } else if is_model(&path) {
+            if let Ok(relative) = path.strip_prefix(root) {
+                found.push(relative.to_string_lossy().replace('\\', "/"));
+            }
+        }
+    }
+}
+
+// Model files that have started loading but aren't in the model list yet
+#[derive(Resource, Default)]
+pub struct ModelLoads {
+    pending: Vec<Handle<Gltf>>,
+}
+
+impl ModelLoads {
+    pub fn start(&mut self, asset_server: &AssetServer, path: String) {
+        let handle = asset_server.load(path);
+        if !self.pending.contains(&handle) {
+            self.pending.push(handle);
+        }
+    }
+
+    pub fn is_loading(&self, path: &str) -> bool {
+        self.pending
+            .iter()
+            .any(|handle| handle.path().is_some_and(|p| p.to_string() == path))
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+pub struct LoadFailure {
+    pub path: String,
+    pub error: String,
+    // Kept so the file watcher can retry the load once the file is fixed
+    handle: Handle<Gltf>,
+}
+
+#[derive(Resource, Default)]
+pub struct LoadReport {
+    pub failures: Vec<LoadFailure>,
+}
+
+impl LoadReport {
+    pub fn has_failed(&self, path: &str) -> bool {
+        self.failures.iter().any(|failure| failure.path == path)
+    }
+
+    pub fn forget(&mut self, path: &str) {
+        self.failures.retain(|failure| failure.path != path);
+    }
+
+    pub fn summary(&self) -> String {
+        self.failures
+            .iter()
+            .map(|failure| failure.error.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+fn register_model(
+    commands: &mut Commands,
+    handle: &Handle<Gltf>,
+    model_list: &mut LoadedModelList,
+    list_parent: Entity,
+) {
+    if !model_list.0.contains(handle) {
+        model_list.0.push(handle.clone());
+        spawn_model(commands.entity(list_parent), handle.clone());
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn track_model_loads(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    import_errors: Res<ImportErrors>,
+    mut loads: ResMut<ModelLoads>,
+    mut report: ResMut<LoadReport>,
+    mut model_list: ResMut<LoadedModelList>,
+    model_list_parent: Query<Entity, With<ModelListParent>>,
+    missing_models: Query<(Entity, &PlacedModel), With<MissingModel>>,
+) {
+    let Ok(list_parent) = model_list_parent.get_single() else {
+        return;
+    };
+    let mut loaded = Vec::new();
+    let pending = std::mem::take(&mut loads.pending);
+    for handle in pending {
+        let path = handle.path().map(|p| p.to_string()).unwrap_or_default();
+        match asset_server.get_load_state(&handle) {
+            Some(LoadState::Loaded) => loaded.push(handle),
+            Some(LoadState::Failed) => {
+                // Bevy doesn't say why a load failed, only the scan loaders do
+                let error = match import_errors.take(&path) {
+                    Some(error) => format!("{path}: {error}"),
+                    None => format!("failed to load {path}"),
+                };
+                error!("Skipping {path}, it failed to load");
+                report.forget(&path);
+                report.failures.push(LoadFailure {
+                    error,
+                    path,
+                    handle,
+                });
+            }
+            _ => loads.pending.push(handle),
+        }
+    }
+    // A failed file that was fixed gets reloaded by the file watcher
+    let recovered: Vec<Handle<Gltf>> = report
+        .failures
+        .iter()
+        .filter(|failure| asset_server.get_load_state(&failure.handle) == Some(LoadState::Loaded))
+        .map(|failure| failure.handle.clone())
+        .collect();
+    for handle in recovered {
+        report.failures.retain(|failure| failure.handle != handle);
+        loaded.push(handle);
+    }
+    for handle in loaded {
+        register_model(&mut commands, &handle, &mut model_list, list_parent);
+        for (entity, model) in &missing_models {
+            if model.0 == handle {
+                commands.entity(entity).remove::<MissingModel>();
+            }
+        }
+    }
+}
+
+#[derive(Component)]
+struct ErrorPanel;
+
+#[derive(Component)]
+struct ErrorPanelText;
+
+#[derive(Component)]
+struct DismissButton;
+
+fn spawn_error_panel(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(10.),
+                    top: Val::Px(10.),
+                    max_width: Val::Percent(50.),
+                    flex_direction: FlexDirection::Column,
+                    padding: UiRect::all(Val::Px(8.)),
+                    row_gap: Val::Px(6.),
+                    ..default()
+                },
+                background_color: Color::rgba(0.4, 0.05, 0.05, 0.9).into(),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            Pickable::IGNORE,
+            ErrorPanel,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 16.,
+                        ..default()
+                    },
+                ),
+                ErrorPanelText,
+            ));
+            parent
+                .spawn((
+                    ButtonBundle {
+                        style: Style {
+                            align_self: AlignSelf::End,
+                            padding: UiRect::axes(Val::Px(8.), Val::Px(2.)),
+                            ..default()
+                        },
+                        background_color: NORMAL_BUTTON.into(),
+                        ..default()
+                    },
+                    DismissButton,
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        "Dismiss",
+                        TextStyle {
+                            font_size: 16.,
+                            ..default()
+                        },
+                    ));
+                });
+        });
+}
+
+fn update_error_panel(
+    report: Res<LoadReport>,
+    mut panel: Query<&mut Visibility, With<ErrorPanel>>,
+    mut text: Query<&mut Text, With<ErrorPanelText>>,
+) {
+    let (Ok(mut visibility), Ok(mut text)) = (panel.get_single_mut(), text.get_single_mut()) else {
+        return;
+    };
+    if report.failures.is_empty() {
+        *visibility = Visibility::Hidden;
+        return;
+    }
+    text.sections[0].value = format!(
+        "{} model file(s) failed to load:\n{}",
+        report.failures.len(),
+        report.summary()
+    );
+    *visibility = Visibility::Inherited;
+}
+
+#[allow(clippy::type_complexity)]
+fn dismiss_error_panel(
+    mut buttons: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<DismissButton>),
+    >,
+    mut panel: Query<&mut Visibility, With<ErrorPanel>>,
+) {
+    for (interaction, mut color) in &mut buttons {
+        match *interaction {
+            Interaction::Pressed => {
+                for mut visibility in &mut panel {
+                    *visibility = Visibility::Hidden;
+                }
+            }
+            Interaction::Hovered => *color = HOVERED_BUTTON.into(),
+            Interaction::None => *color = NORMAL_BUTTON.into(),
+        }
+    }
+}
@@ -1,52 +1,94 @@
-use std::{collections::HashMap, f32::consts::PI, time::Duration};
+use std::{collections::HashMap, f32::consts::PI};
 
 use bevy::{
     a11y::{
         accesskit::{NodeBuilder, Role},
         AccessibilityNode,
     },
-    asset::LoadedFolder,
     core_pipeline::clear_color::ClearColorConfig,
     ecs::system::EntityCommands,
     gltf::Gltf,
     input::mouse::{MouseScrollUnit, MouseWheel},
     math::{vec4, DVec2},
-    pbr::NotShadowCaster,
     prelude::*,
     render::primitives::Aabb,
     window::{CursorGrabMode, PrimaryWindow},
 };
-use bevy_flycam::{FlyCam, KeyBindings, MovementSettings, NoCameraPlayerPlugin};
-use bevy_framepace::FramepaceSettings;
+use bevy_flycam::{FlyCam, NoCameraPlayerPlugin};
+use bevy_framepace::FramepacePlugin;
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use bevy_mod_picking::{backends::raycast::bevy_mod_raycast::prelude::SimplifiedMesh, prelude::*};
+use drag::{move_model, ModelMoveEvent};
+use loading::{LoadReport, ModelLoads};
+use placement::{GhostPreview, SidebarDrag};
+use room::Room;
+
+mod bookmarks;
+mod calibration;
+mod camera;
+mod collision;
+mod cutaway;
+mod drag;
+mod edit;
+mod gizmo;
+mod history;
+mod import;
+mod layout;
+mod library;
+mod loading;
+mod measure;
+mod metadata;
+mod openings;
+mod pivot;
+mod placement;
+mod reload;
+mod room;
+mod selection;
+mod settings;
+mod snap;
+mod surface;
+mod walk;
 
 fn main() {
     App::new()
+        .add_plugins(library::LibraryPlugin)
         .add_plugins(DefaultPlugins)
         .add_plugins(NoCameraPlayerPlugin)
+        .add_plugins(FramepacePlugin)
         // .add_plugins(WorldInspectorPlugin::default())
         .add_plugins(
             DefaultPickingPlugins
                 .build()
-                .disable::<DebugPickingPlugin>(),
+                .disable::<DebugPickingPlugin>()
+                // Replaced by `selection`, which selects whole models
+                .disable::<SelectionPlugin>(),
         )
+        .add_plugins(import::ImportPlugin)
+        .add_plugins(loading::LoadingPlugin)
+        .add_plugins(layout::LayoutPlugin)
+        .add_plugins(history::HistoryPlugin)
+        .add_plugins(selection::ModelSelectionPlugin)
+        .add_plugins(gizmo::TransformGizmoPlugin)
+        .add_plugins(collision::CollisionPlugin)
+        .add_plugins(surface::SurfacePlugin)
+        .add_plugins(pivot::PivotPlugin)
+        .add_plugins(metadata::MetadataPlugin)
+        .add_plugins(calibration::CalibrationPlugin)
+        .add_plugins(measure::MeasurePlugin)
+        .add_plugins(camera::CameraPlugin)
+        .add_plugins(bookmarks::BookmarksPlugin)
+        .add_plugins(walk::WalkPlugin)
+        .add_plugins(cutaway::CutawayPlugin)
+        .add_plugins(edit::EditPlugin)
+        .add_plugins(placement::PlacementPlugin)
+        .add_plugins(room::RoomPlugin)
+        .add_plugins(openings::OpeningsPlugin)
+        .add_plugins(reload::ReloadPlugin)
+        .add_plugins(settings::SettingsPlugin)
         .add_state::<LoadingState>()
-        .insert_resource(MovementSettings {
-            sensitivity: 0.00015, // default: 0.00012
-            speed: 12.0,          // default: 12.0
-        })
-        .insert_resource(KeyBindings {
-            move_ascend: KeyCode::E,
-            move_descend: KeyCode::Q,
-            toggle_grab_cursor: KeyCode::R,
-            ..Default::default()
-        })
-        .insert_resource(FramepaceSettings {
-            limiter: bevy_framepace::Limiter::Manual(Duration::from_secs_f32(1./30.)),
-        })
         .init_resource::<LoadedModelList>()
         .init_resource::<AabbMeshMap>()
+        .init_resource::<snap::SnapSettings>()
         .add_event::<ModelMoveEvent>()
         .add_systems(
             Startup,
@@ -67,10 +109,6 @@ fn main() {
             Update,
             check_asset_loading.run_if(in_state(LoadingState::Unloaded)),
         )
-        .add_systems(
-            Update,
-            gltf_asset_event_watcher, //.run_if(in_state(LoadingState::Loaded)),
-        )
         .run();
 }
 
@@ -82,19 +120,13 @@ fn set_title(mut window: Query<&mut Window, With<PrimaryWindow>>) {
     window.title = "DECO.ai".to_string();
 }
 
-fn spawn_inital_scene(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    asset_server: Res<AssetServer>,
-) {
-    const W: f32 = 40.;
-    const L: f32 = 50.;
-    const H: f32 = 20.;
+fn spawn_inital_scene(mut commands: Commands, asset_server: Res<AssetServer>, room: Res<Room>) {
     let skybox_handle: Handle<Image> = asset_server.load("images/Ryfjallet_cubemap.png");
     commands.spawn((
         Camera3dBundle {
-            transform: Transform::from_xyz(10., 2. * H, 10.0).looking_at(Vec3::ZERO, Vec3::Y),
+            // Above a corner outside the room, looking down into it
+            transform: Transform::from_xyz(4., 2. * room.wall_height, 5.)
+                .looking_at(Vec3::ZERO, Vec3::Y),
             camera_3d: Camera3d {
                 clear_color: ClearColorConfig::Custom(Color::rgb(0.3, 0.6, 0.85)),
                 ..Default::default()
@@ -105,107 +137,6 @@ fn spawn_inital_scene(
         FlyCam,
     ));
     Box::leak(Box::new(skybox_handle));
-    // Floor
-    commands.spawn((
-        PbrBundle {
-            mesh: meshes.add(shape::Quad::new(Vec2::new(L, W)).into()),
-            material: materials.add(StandardMaterial {
-                base_color_texture: Some(asset_server.load("images/wood.png")),
-                perceptual_roughness: 0.8,
-                cull_mode: None,
-                ..default()
-            }),
-            transform: Transform::from_xyz(0., 0., 0.).with_rotation(Quat::from_euler(
-                EulerRot::XYZ,
-                -PI / 2.,
-                0.,
-                PI / 2.,
-            )),
-            ..default()
-        },
-        Pickable::IGNORE,
-        NotShadowCaster,
-    ));
-    commands.spawn((
-        PbrBundle {
-            mesh: meshes.add(shape::Quad::new(Vec2::new(L, H)).into()),
-            material: materials.add(StandardMaterial {
-                base_color_texture: Some(asset_server.load("images/wallpaper.png")),
-                perceptual_roughness: 0.8,
-                cull_mode: None,
-                ..default()
-            }),
-            transform: Transform::from_xyz(W / 2., H / 2., 0.).with_rotation(Quat::from_euler(
-                EulerRot::XYZ,
-                -PI / 2.,
-                -PI / 2.,
-                PI / 2.,
-            )),
-            ..default()
-        },
-        Pickable::IGNORE,
-        NotShadowCaster,
-    ));
-    commands.spawn((
-        PbrBundle {
-            mesh: meshes.add(shape::Quad::new(Vec2::new(W, H)).into()),
-            material: materials.add(StandardMaterial {
-                base_color_texture: Some(asset_server.load("images/wallpaper.png")),
-                perceptual_roughness: 0.8,
-                cull_mode: None,
-                ..default()
-            }),
-            transform: Transform::from_xyz(0., H / 2., L / 2.).with_rotation(Quat::from_euler(
-                EulerRot::XYZ,
-                0.,
-                0.,
-                0.,
-            )),
-            ..default()
-        },
-        Pickable::IGNORE,
-        NotShadowCaster,
-    ));
-    commands.spawn((
-        PbrBundle {
-            mesh: meshes.add(shape::Quad::new(Vec2::new(L, H)).into()),
-            material: materials.add(StandardMaterial {
-                base_color_texture: Some(asset_server.load("images/wallpaper.png")),
-                perceptual_roughness: 0.8,
-                cull_mode: None,
-                ..default()
-            }),
-            transform: Transform::from_xyz(-W / 2., H / 2., 0.).with_rotation(Quat::from_euler(
-                EulerRot::XYZ,
-                -PI / 2.,
-                -PI / 2.,
-                PI / 2.,
-            )),
-            ..default()
-        },
-        Pickable::IGNORE,
-        NotShadowCaster,
-    ));
-    commands.spawn((
-        PbrBundle {
-            mesh: meshes.add(shape::Quad::new(Vec2::new(W, H)).into()),
-            material: materials.add(StandardMaterial {
-                base_color_texture: Some(asset_server.load("images/wallpaper.png")),
-                perceptual_roughness: 0.8,
-                cull_mode: None,
-                ..default()
-            }),
-            transform: Transform::from_xyz(0., H / 2., -L / 2.).with_rotation(Quat::from_euler(
-                EulerRot::XYZ,
-                0.,
-                0.,
-                0.,
-            )),
-            ..default()
-        },
-        Pickable::IGNORE,
-        NotShadowCaster,
-    ));
 
     // Light
     commands.spawn(DirectionalLightBundle {
@@ -246,12 +177,79 @@ enum LoadingState {
     Loaded,
 }
 
-#[derive(Debug, Resource)]
-struct AssetFolder(Handle<LoadedFolder>);
-
 #[derive(Debug, Resource, Default)]
 struct LoadedModelList(Vec<Handle<Gltf>>);
 
+impl LoadedModelList {
+    fn find_by_path(&self, path: &str) -> Option<&Handle<Gltf>> {
+        self.0
+            .iter()
+            .find(|handle| handle.path().is_some_and(|p| p.to_string() == path))
+    }
+}
+
+#[derive(Component)]
+struct PlacedModel(Handle<Gltf>);
+
+// Picking hits the meshes inside a model's scene, this finds the placed model they belong to
+fn placed_model_root(
+    mut entity: Entity,
+    parents: &Query<&Parent>,
+    placed_models: &Query<(), With<PlacedModel>>,
+) -> Option<Entity> {
+    loop {
+        if placed_models.contains(entity) {
+            return Some(entity);
+        }
+        entity = parents.get(entity).ok()?.get();
+    }
+}
+
+// Bounds of a placed model's meshes in its own coordinates, before its
+// transform is applied. Empty until its scene has spawned.
+fn model_bounds(
+    model: Entity,
+    model_transform: &GlobalTransform,
+    children: &Query<&Children>,
+    meshes: &Query<(&Aabb, &GlobalTransform)>,
+) -> Option<(Vec3, Vec3)> {
+    let to_model = model_transform.affine().inverse();
+    let mut bounds: Option<(Vec3, Vec3)> = None;
+    for (aabb, transform) in meshes.iter_many(children.iter_descendants(model)) {
+        let (center, half) = (Vec3::from(aabb.center), Vec3::from(aabb.half_extents));
+        for corner in 0..8 {
+            let sign = Vec3::new(
+                if corner & 1 == 0 { -1. } else { 1. },
+                if corner & 2 == 0 { -1. } else { 1. },
+                if corner & 4 == 0 { -1. } else { 1. },
+            );
+            let point = to_model.transform_point3(transform.transform_point(center + sign * half));
+            bounds = Some(match bounds {
+                Some((min, max)) => (min.min(point), max.max(point)),
+                None => (point, point),
+            });
+        }
+    }
+    bounds
+}
+
+// The world-aligned box around a model with the given bounds
+fn world_bounds(bounds: (Vec3, Vec3), transform: &Transform) -> (Vec3, Vec3) {
+    let (min, max) = bounds;
+    (0..8)
+        .map(|corner| {
+            transform.transform_point(Vec3::new(
+                if corner & 1 == 0 { min.x } else { max.x },
+                if corner & 2 == 0 { min.y } else { max.y },
+                if corner & 4 == 0 { min.z } else { max.z },
+            ))
+        })
+        .fold(
+            (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)),
+            |(low, high), corner| (low.min(corner), high.max(corner)),
+        )
+}
+
 #[derive(Component)]
 struct ModelListParent;
 
@@ -269,20 +267,15 @@ struct ListItemModel(Handle<Gltf>);
 #[derive(Resource, Default)]
 struct AabbMeshMap(HashMap<Handle<Mesh>, Handle<Mesh>>);
 
-#[derive(Event)]
-struct ModelMoveEvent(Entity, Drag);
-
-impl From<ListenerInput<Pointer<Drag>>> for ModelMoveEvent {
-    fn from(value: ListenerInput<Pointer<Drag>>) -> Self {
-        Self(value.listener(), (**value).clone())
+fn model_loader(asset_server: Res<AssetServer>, mut loads: ResMut<ModelLoads>) {
+    let assets = loading::asset_dir();
+    let mut files = Vec::new();
+    loading::find_models(&assets, &assets.join(loading::MODEL_DIR), &mut files);
+    for path in files {
+        loads.start(&asset_server, path);
     }
 }
 
-fn model_loader(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let folder = asset_server.load_folder("models");
-    commands.insert_resource(AssetFolder(folder));
-}
-
 const RIGHT_SIDEBAR_WIDTH: f32 = 250.;
 
 fn spawn_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
@@ -460,8 +453,6 @@ fn button_system(
         (Changed<Interaction>, With<Button>),
     >,
     mut model_query: Query<&ListItemModel>,
-    gltf_assets: Res<Assets<Gltf>>,
-    camera_pos: Query<&GlobalTransform, With<Camera3d>>,
 ) {
     for (interaction, mut color, children) in &mut interaction_query {
         let Ok(model) = model_query.get_mut(children[0]) else {
@@ -469,33 +460,8 @@ fn button_system(
         };
         match *interaction {
             Interaction::Pressed => {
-                let Ok(camera_pos) = camera_pos.get_single() else {
-                    error!("Unable to find camera transform while spawning model");
-                    continue;
-                };
-                let mut transform = camera_pos.compute_transform();
-                let looking = transform.rotation * Vec3::Z;
-                transform.translation -= transform.translation.y * looking * 1.5;
-                transform.translation.y = 0.;
-                let rot_y = transform.rotation.to_euler(EulerRot::XYZ).1;
-                transform.rotation = Quat::from_euler(EulerRot::XYZ, 0., rot_y, 0.);
-                let mesh = gltf_assets
-                    .get(&model.0)
-                    .expect(&format!("Expected to find asset",));
-                let scene = mesh
-                    .default_scene
-                    .as_ref()
-                    .or(mesh.scenes.first())
-                    .expect("Expected model to have at least one scene")
-                    .clone();
-                commands.spawn((
-                    SceneBundle {
-                        scene,
-                        transform,
-                        ..default()
-                    },
-                    On::<Pointer<Drag>>::send_event::<ModelMoveEvent>(),
-                ));
+                // The model is placed when the button is released, see `placement`
+                commands.insert_resource(SidebarDrag::new(model.0.clone()));
                 // text.sections[0].value = "Press".to_string();
                 *color = PRESSED_BUTTON.into();
                 // border_color.0 = Color::RED;
@@ -513,100 +479,53 @@ fn button_system(
     }
 }
 
-fn move_model(
-    mut models: Query<
-        &mut Transform,
-        (
-            With<Handle<Scene>>,
-            With<On<Pointer<Drag>>>,
-            Without<Camera3d>,
-        ),
-    >,
-    camera: Query<&Transform, With<Camera3d>>,
-    mut move_events: EventReader<ModelMoveEvent>,
-) {
-    let Ok(camera) = camera.get_single() else {
-        error!("No camera!?");
-        return;
+fn model_scene(gltf_assets: &Assets<Gltf>, model: &Handle<Gltf>) -> Option<Handle<Scene>> {
+    let Some(gltf) = gltf_assets.get(model) else {
+        error!("Expected to find asset {:?}", model.path());
+        return None;
     };
-    let camera_rotation = camera.rotation.to_euler(EulerRot::XYZ).2;
-    let (sin, cos) = camera_rotation.sin_cos();
-    for event in move_events.read() {
-        let Ok(mut model) = models.get_mut(event.0) else {
-            error!("Event for nonexistent model");
-            continue;
-        };
-        let delta = event.1.delta;
-        match event.1.button {
-            PointerButton::Primary => {
-                model.translation.x += (delta.x * cos + delta.y * sin) * 0.05;
-                model.translation.z += (-delta.x * sin + delta.y * cos) * 0.03;
-            }
-            PointerButton::Secondary => {
-                model.rotate_local_y(event.1.delta.x / 50.0);
-                model.scale *= (event.1.delta.y / -100.).exp().min(10.);
-            }
-            PointerButton::Middle => {
-                model.translation.y += event.1.delta.y * -0.05;
-            }
-        }
-    }
+    let Some(scene) = gltf.default_scene.as_ref().or(gltf.scenes.first()) else {
+        error!(
+            "Expected model {:?} to have at least one scene",
+            model.path()
+        );
+        return None;
+    };
+    Some(scene.clone())
 }
 
-fn check_asset_loading(
-    mut commands: Commands,
-    server: Res<AssetServer>,
-    loading: Res<AssetFolder>,
-) {
-    let loading = loading.0.clone();
-    match server.load_state(loading) {
-        bevy::asset::LoadState::Loaded => {
-            println!("All assets loaded!");
-            commands.insert_resource(NextState(Some(LoadingState::Loaded)))
-        }
-        bevy::asset::LoadState::Failed => panic!("Unable to load all assets"),
-        _ => {}
-    }
+fn spawn_placed_model(
+    commands: &mut Commands,
+    gltf_assets: &Assets<Gltf>,
+    model: Handle<Gltf>,
+    transform: Transform,
+) -> Option<Entity> {
+    let scene = model_scene(gltf_assets, &model)?;
+    let entity = commands
+        .spawn((
+            SpatialBundle::from_transform(transform),
+            On::<Pointer<Drag>>::send_event::<ModelMoveEvent>(),
+            On::<Pointer<DragStart>>::send_event::<history::GestureStartEvent>(),
+            On::<Pointer<DragEnd>>::send_event::<history::GestureEndEvent>(),
+            PlacedModel(model.clone()),
+        ))
+        .with_children(|parent| {
+            parent.spawn(pivot::model_scene_bundle(model.clone(), scene));
+        })
+        .id();
+    Some(entity)
 }
 
-fn gltf_asset_event_watcher(
-    mut commands: Commands,
-    mut gltf_events: EventReader<AssetEvent<Gltf>>,
-    folder_resource: Res<AssetFolder>,
-    mut gltf_resource: ResMut<LoadedModelList>,
-    folder_asset: Res<Assets<LoadedFolder>>,
-    model_list_parent: Query<Entity, With<ModelListParent>>,
-    mut event_queue: Local<Vec<AssetEvent<Gltf>>>,
-) {
-    let Some(loaded_folder): Option<&LoadedFolder> = folder_asset.get(&folder_resource.0) else {
-        event_queue.extend(gltf_events.read());
-        return;
-    };
-    let size_before = gltf_resource.0.len();
-    let Ok(parent) = model_list_parent.get_single() else {
+fn check_asset_loading(mut commands: Commands, loads: Res<ModelLoads>, report: Res<LoadReport>) {
+    if !loads.is_idle() {
         return;
-    };
-    for gevent in event_queue.iter().chain(gltf_events.read()) {
-        dbg!(gevent);
-        match gevent {
-            AssetEvent::Added { id } => {
-                let Some(gltf_asset) = loaded_folder
-                    .handles
-                    .iter()
-                    .find(|handle| handle.id() == id.untyped())
-                else {
-                    continue;
-                };
-                gltf_resource.0.push(gltf_asset.clone().typed());
-                spawn_model(commands.entity(parent), gltf_asset.clone().typed());
-            }
-            _ => {}
-        }
     }
-    event_queue.clear();
-    if gltf_resource.0.len() != size_before {
-        dbg!(gltf_resource);
+    if report.failures.is_empty() {
+        info!("All assets loaded!");
+    } else {
+        warn!("Some models failed to load:\n{}", report.summary());
     }
+    commands.insert_resource(NextState(Some(LoadingState::Loaded)))
 }
 
 fn spawn_model(mut entity_commands: EntityCommands, gltf_asset: Handle<Gltf>) {
@@ -685,8 +604,17 @@ fn make_pickable(
     meshes: Query<(Entity, &Aabb, &Handle<Mesh>), Without<Pickable>>,
     mut mesh_assets: ResMut<Assets<Mesh>>,
     mut aabb_mesh_map: ResMut<AabbMeshMap>,
+    parents: Query<&Parent>,
+    ghosts: Query<(), With<GhostPreview>>,
 ) {
     for (entity, aabb, mesh) in meshes.iter() {
+        let is_ghost =
+            std::iter::successors(Some(entity), |e| parents.get(*e).ok().map(Parent::get))
+                .any(|e| ghosts.contains(e));
+        if is_ghost {
+            commands.entity(entity).insert(Pickable::IGNORE);
+            continue;
+        }
         let aabb_box = shape::Box::from_corners(
             (aabb.center - aabb.half_extents).into(),
             (aabb.center + aabb.half_extents).into(),
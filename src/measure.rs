@@ -0,0 +1,363 @@
+use bevy::{
+    prelude::*,
+    render::primitives::Aabb,
+    window::{CursorGrabMode, PrimaryWindow},
+};
+use bevy_mod_picking::{backends::raycast::bevy_mod_raycast::prelude::Ray3d, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cutaway::HiddenWalls, history::ActiveGestures, model_bounds, placed_model_root, room::Room,
+    settings::Settings, surface::Surfaces, world_bounds, LoadingState, PlacedModel,
+    RIGHT_SIDEBAR_WIDTH,
+};
+
+const INCHES_PER_METRE: f32 = 1. / 0.0254;
+const LABEL_COLOR: Color = Color::rgb(1., 0.85, 0.2);
+
+// M toggles the ruler, which measures between two clicked points on models,
+// walls or the floor. Hovering a model shows its size and dragging one shows
+// the gaps to the walls around it. Shift+M switches between metric and
+// imperial units, which are kept in the settings.
+pub struct MeasurePlugin;
+
+impl Plugin for MeasurePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Units>()
+            .init_resource::<Ruler>()
+            .init_resource::<Labels>()
+            .add_systems(
+                Update,
+                (
+                    measure_keys,
+                    ruler,
+                    hover_dimensions,
+                    wall_gaps,
+                    show_labels,
+                )
+                    .chain()
+                    .run_if(in_state(LoadingState::Loaded)),
+            );
+    }
+}
+
+// Set from `Settings::units`
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Units {
+    #[default]
+    Metric,
+    Imperial,
+}
+
+impl Units {
+    pub fn format(self, metres: f32) -> String {
+        match self {
+            Units::Metric if metres.abs() < 1. => format!("{:.1} cm", metres * 100.),
+            Units::Metric => format!("{metres:.2} m"),
+            Units::Imperial => {
+                let inches = (metres * INCHES_PER_METRE).round() as i32;
+                match (inches / 12, inches % 12) {
+                    (0, inches) => format!("{inches}\""),
+                    (feet, inches) => format!("{feet}' {}\"", inches.abs()),
+                }
+            }
+        }
+    }
+
+    pub fn format_speed(self, metres_per_second: f32) -> String {
+        match self {
+            Units::Metric => format!("{metres_per_second:.1} m/s"),
+            Units::Imperial => format!("{:.1} ft/s", metres_per_second * INCHES_PER_METRE / 12.),
+        }
+    }
+
+    pub fn toggled(self) -> Self {
+        match self {
+            Units::Metric => Units::Imperial,
+            Units::Imperial => Units::Metric,
+        }
+    }
+
+    // Typed lengths are in metres or inches
+    pub fn to_metres(self, value: f32) -> f32 {
+        match self {
+            Units::Metric => value,
+            Units::Imperial => value / INCHES_PER_METRE,
+        }
+    }
+
+    pub fn input_name(self) -> &'static str {
+        match self {
+            Units::Metric => "metres",
+            Units::Imperial => "inches",
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+struct Ruler {
+    active: bool,
+    points: Vec<Vec3>,
+}
+
+// Text shown next to points in the world this frame
+#[derive(Resource, Default)]
+struct Labels(Vec<(Vec3, String)>);
+
+#[derive(Component)]
+struct MeasureLabel;
+
+fn measure_keys(
+    keys: Res<Input<KeyCode>>,
+    mut settings: ResMut<Settings>,
+    mut ruler: ResMut<Ruler>,
+) {
+    if keys.just_pressed(KeyCode::Escape) && ruler.active {
+        *ruler = Ruler::default();
+    }
+    if !keys.just_pressed(KeyCode::M) {
+        return;
+    }
+    if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        settings.units = settings.units.toggled();
+        info!("{:?} units", settings.units);
+    } else {
+        ruler.active = !ruler.active;
+        ruler.points.clear();
+        info!("Ruler {}", if ruler.active { "on" } else { "off" });
+    }
+}
+
+// Where the cursor points at a model, a visible wall or the floor, whichever
+// is closest
+fn cursor_hit(ray: Ray, room: &Room, hidden: &HiddenWalls, surfaces: &Surfaces) -> Option<Vec3> {
+    let model = surfaces.model_hit(Ray3d::new(ray.origin, ray.direction), &[]);
+    let wall = room
+        .wall_hit_except(ray, &hidden.0)
+        .map(|hit| ray.get_point(hit.distance));
+    let floor = ray
+        .intersect_plane(Vec3::ZERO, Vec3::Y)
+        .map(|distance| ray.get_point(distance))
+        .filter(|point| room.contains(point.xz()));
+    [model, wall, floor].into_iter().flatten().min_by(|a, b| {
+        a.distance_squared(ray.origin)
+            .total_cmp(&b.distance_squared(ray.origin))
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn ruler(
+    mouse: Res<Input<MouseButton>>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    camera: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+    room: Res<Room>,
+    hidden: Res<HiddenWalls>,
+    surfaces: Surfaces,
+    units: Res<Units>,
+    mut ruler: ResMut<Ruler>,
+    mut labels: ResMut<Labels>,
+    mut gizmos: Gizmos,
+) {
+    labels.0.clear();
+    if !ruler.active {
+        return;
+    }
+    let (Ok(window), Ok((camera, camera_transform))) = (window.get_single(), camera.get_single())
+    else {
+        return;
+    };
+    let hover = window
+        .cursor_position()
+        .filter(|cursor| {
+            window.cursor.grab_mode == CursorGrabMode::None
+                && cursor.x < window.width() - RIGHT_SIDEBAR_WIDTH
+        })
+        .and_then(|cursor| camera.viewport_to_world(camera_transform, cursor))
+        .and_then(|ray| cursor_hit(ray, &room, &hidden, &surfaces));
+    if let (true, Some(point)) = (mouse.just_pressed(MouseButton::Left), hover) {
+        if ruler.points.len() == 2 {
+            ruler.points.clear();
+        }
+        ruler.points.push(point);
+    }
+    // The second end follows the cursor until it is placed
+    let end = match ruler.points[..] {
+        [start] => hover.map(|hover| (start, hover)),
+        [start, end] => Some((start, end)),
+        _ => None,
+    };
+    for point in ruler.points.iter().chain(&hover) {
+        gizmos.sphere(*point, Quat::IDENTITY, 0.02, LABEL_COLOR);
+    }
+    if let Some((start, end)) = end {
+        gizmos.line(start, end, LABEL_COLOR);
+        labels
+            .0
+            .push(((start + end) / 2., units.format(start.distance(end))));
+    }
+}
+
+// Width, depth and height of the model under the cursor, in its own axes
+#[allow(clippy::too_many_arguments)]
+fn hover_dimensions(
+    mut over_events: EventReader<Pointer<Over>>,
+    mut out_events: EventReader<Pointer<Out>>,
+    parents: Query<&Parent>,
+    placed_models: Query<(), With<PlacedModel>>,
+    models: Query<(&Transform, &GlobalTransform), With<PlacedModel>>,
+    children: Query<&Children>,
+    meshes: Query<(&Aabb, &GlobalTransform)>,
+    gestures: Res<ActiveGestures>,
+    units: Res<Units>,
+    mut labels: ResMut<Labels>,
+    mut hovered: Local<Option<Entity>>,
+) {
+    for event in out_events.read() {
+        if placed_model_root(event.target, &parents, &placed_models) == *hovered {
+            *hovered = None;
+        }
+    }
+    for event in over_events.read() {
+        if let Some(model) = placed_model_root(event.target, &parents, &placed_models) {
+            *hovered = Some(model);
+        }
+    }
+    let Some(model) = *hovered else {
+        return;
+    };
+    if gestures.models().next().is_some() {
+        return;
+    }
+    let Ok((transform, global)) = models.get(model) else {
+        *hovered = None;
+        return;
+    };
+    let Some((min, max)) = model_bounds(model, global, &children, &meshes) else {
+        return;
+    };
+    let size = (max - min) * transform.scale.abs();
+    let top =
+        transform.transform_point(Vec3::new((min.x + max.x) / 2., max.y, (min.z + max.z) / 2.));
+    labels.0.push((
+        top,
+        format!(
+            "{} × {} × {}",
+            units.format(size.x),
+            units.format(size.z),
+            units.format(size.y)
+        ),
+    ));
+}
+
+// Lines from each side of the dragged models to the wall it faces
+#[allow(clippy::too_many_arguments)]
+fn wall_gaps(
+    gestures: Res<ActiveGestures>,
+    models: Query<(&Transform, &GlobalTransform), With<PlacedModel>>,
+    children: Query<&Children>,
+    meshes: Query<(&Aabb, &GlobalTransform)>,
+    room: Res<Room>,
+    units: Res<Units>,
+    mut labels: ResMut<Labels>,
+    mut gizmos: Gizmos,
+) {
+    for model in gestures.models() {
+        let Ok((transform, global)) = models.get(model) else {
+            continue;
+        };
+        let Some(bounds) = model_bounds(model, global, &children, &meshes) else {
+            continue;
+        };
+        let (low, high) = world_bounds(bounds, transform);
+        let center = (low + high) / 2.;
+        let height = center.y.clamp(0., room.wall_height);
+        for direction in [Vec3::X, Vec3::NEG_X, Vec3::Z, Vec3::NEG_Z] {
+            let side = center + direction * ((high - low) / 2.).dot(direction.abs());
+            let side = Vec3::new(side.x, height, side.z);
+            if !room.contains(side.xz()) {
+                continue;
+            }
+            let ray = Ray {
+                origin: side,
+                direction,
+            };
+            let Some(hit) = room.wall_hit(ray) else {
+                continue;
+            };
+            let wall = ray.get_point(hit.distance);
+            gizmos.line(side, wall, LABEL_COLOR);
+            labels
+                .0
+                .push(((side + wall) / 2., units.format(hit.distance)));
+        }
+    }
+}
+
+// Keeps one text node per label over the point it belongs to
+fn show_labels(
+    mut commands: Commands,
+    labels: Res<Labels>,
+    camera: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+    mut nodes: Query<(&mut Text, &mut Style, &mut Visibility), With<MeasureLabel>>,
+) {
+    let Ok((camera, camera_transform)) = camera.get_single() else {
+        return;
+    };
+    let mut positions = labels.0.iter().filter_map(|(point, text)| {
+        let position = camera.world_to_viewport(camera_transform, *point)?;
+        Some((position, text))
+    });
+    for (mut text, mut style, mut visibility) in &mut nodes {
+        match positions.next() {
+            Some((position, value)) => {
+                text.sections[0].value = value.clone();
+                style.left = Val::Px(position.x);
+                style.top = Val::Px(position.y);
+                *visibility = Visibility::Inherited;
+            }
+            None => *visibility = Visibility::Hidden,
+        }
+    }
+    for (position, value) in positions {
+        commands.spawn((
+            TextBundle::from_section(
+                value.clone(),
+                TextStyle {
+                    font_size: 16.,
+                    color: LABEL_COLOR,
+                    ..default()
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(position.x),
+                top: Val::Px(position.y),
+                ..default()
+            })
+            .with_background_color(Color::rgba(0., 0., 0., 0.6)),
+            Pickable::IGNORE,
+            MeasureLabel,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_metric() {
+        assert_eq!(Units::Metric.format(0.456), "45.6 cm");
+        assert_eq!(Units::Metric.format(1.), "1.00 m");
+        assert_eq!(Units::Metric.format(2.345), "2.35 m");
+        assert_eq!(Units::Metric.format(-0.5), "-50.0 cm");
+    }
+
+    #[test]
+    fn formats_imperial() {
+        assert_eq!(Units::Imperial.format(0.1), "4\"");
+        assert_eq!(Units::Imperial.format(0.3048), "1' 0\"");
+        assert_eq!(Units::Imperial.format(1.), "3' 3\"");
+        assert_eq!(Units::Imperial.format(-1.), "-3' 3\"");
+    }
+}
@@ -0,0 +1,126 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::{ecs::system::SystemParam, gltf::Gltf, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{library::ModelLibrary, selection::Selection, LoadingState, PlacedModel};
+
+// Per model file settings, kept in a `<file>.deco.ron` file next to the
+// model so they travel with it. L toggles the scale lock of the selected
+// models.
+pub struct MetadataPlugin;
+
+impl Plugin for MetadataPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MetadataCache>().add_systems(
+            Update,
+            toggle_scale_lock.run_if(in_state(LoadingState::Loaded)),
+        );
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelMetadata {
+    // Scans of real objects are already the right size, so scaling them is
+    // blocked unless this is turned off
+    pub true_scale: bool,
+    // Converts the file's units to metres, set by calibration
+    pub base_scale: f32,
+}
+
+impl Default for ModelMetadata {
+    fn default() -> Self {
+        Self {
+            true_scale: true,
+            base_scale: 1.,
+        }
+    }
+}
+
+pub fn metadata_file(model_file: &Path) -> PathBuf {
+    let mut name = model_file.file_name().unwrap_or_default().to_os_string();
+    name.push(".deco.ron");
+    model_file.with_file_name(name)
+}
+
+#[derive(Resource, Default)]
+pub struct MetadataCache(HashMap<String, ModelMetadata>);
+
+#[derive(SystemParam)]
+pub struct Metadata<'w> {
+    cache: ResMut<'w, MetadataCache>,
+    library: Res<'w, ModelLibrary>,
+}
+
+impl Metadata<'_> {
+    // Read from disk the first time a model is asked about
+    pub fn get(&mut self, model: &Handle<Gltf>) -> ModelMetadata {
+        let Some(path) = model.path().map(|path| path.to_string()) else {
+            return ModelMetadata::default();
+        };
+        let library = &self.library;
+        *self.cache.0.entry(path).or_insert_with_key(|path| {
+            let file = metadata_file(&library.file_path(path));
+            let Ok(text) = fs::read_to_string(&file) else {
+                return ModelMetadata::default();
+            };
+            ron::from_str(&text).unwrap_or_else(|err| {
+                warn!("Ignoring invalid model metadata {}: {err}", file.display());
+                ModelMetadata::default()
+            })
+        })
+    }
+
+    pub fn set(&mut self, model: &Handle<Gltf>, metadata: ModelMetadata) {
+        let Some(path) = model.path().map(|path| path.to_string()) else {
+            return;
+        };
+        let file = metadata_file(&self.library.file_path(&path));
+        let result = ron::ser::to_string_pretty(&metadata, ron::ser::PrettyConfig::default())
+            .map_err(|err| err.to_string())
+            .and_then(|text| fs::write(&file, text).map_err(|err| err.to_string()));
+        if let Err(err) = result {
+            error!("Unable to save model metadata {}: {err}", file.display());
+        }
+        self.cache.0.insert(path, metadata);
+    }
+}
+
+fn toggle_scale_lock(
+    keys: Res<Input<KeyCode>>,
+    selection: Res<Selection>,
+    models: Query<&PlacedModel>,
+    mut metadata: Metadata,
+) {
+    if !keys.just_pressed(KeyCode::L) {
+        return;
+    }
+    let mut toggled: Vec<&Handle<Gltf>> = Vec::new();
+    for model in models.iter_many(selection.models()) {
+        if toggled.contains(&&model.0) {
+            continue;
+        }
+        toggled.push(&model.0);
+        let mut model_metadata = metadata.get(&model.0);
+        model_metadata.true_scale = !model_metadata.true_scale;
+        metadata.set(&model.0, model_metadata);
+        info!(
+            "{} {}",
+            model
+                .0
+                .path()
+                .map(|path| path.to_string())
+                .unwrap_or_default(),
+            if model_metadata.true_scale {
+                "is locked to its true scale"
+            } else {
+                "can be scaled"
+            }
+        );
+    }
+}
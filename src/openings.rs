@@ -0,0 +1,358 @@
+use bevy::{
+    prelude::*,
+    render::primitives::Aabb,
+    window::{CursorGrabMode, PrimaryWindow},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cutaway::HiddenWalls,
+    measure::Units,
+    model_bounds,
+    room::{Room, WallHit},
+    world_bounds, LoadingState, PlacedModel,
+};
+
+// How much the opening keys change a size
+const OPENING_STEP: f32 = 0.05;
+const MIN_OPENING_SIZE: f32 = 0.2;
+// Straight pieces a door swing's curve is drawn and tested with
+const SWING_SEGMENTS: usize = 16;
+const ADJUST_KEYS: [KeyCode; 7] = [
+    KeyCode::Left,
+    KeyCode::Right,
+    KeyCode::Up,
+    KeyCode::Down,
+    KeyCode::PageUp,
+    KeyCode::PageDown,
+    KeyCode::Tab,
+];
+
+pub struct OpeningsPlugin;
+
+impl Plugin for OpeningsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                edit_openings.run_if(in_state(LoadingState::Loaded)),
+                draw_door_swings,
+            ),
+        );
+    }
+}
+
+// A door or window cut into wall `wall` of the room outline
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WallOpening {
+    pub wall: usize,
+    // Distance along the wall from its start corner to the middle of the opening
+    pub offset: f32,
+    pub width: f32,
+    pub height: f32,
+    pub sill_height: f32,
+    pub kind: OpeningKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum OpeningKind {
+    Window,
+    Door { hinge: DoorHinge, swing: DoorSwing },
+}
+
+// Which side of the opening the hinge is on, following the wall from its start corner
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DoorHinge {
+    Start,
+    End,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DoorSwing {
+    Inward,
+    Outward,
+}
+
+impl WallOpening {
+    pub fn window(wall: usize, offset: f32) -> Self {
+        Self {
+            wall,
+            offset,
+            width: 1.2,
+            height: 1.2,
+            sill_height: 0.9,
+            kind: OpeningKind::Window,
+        }
+    }
+
+    pub fn door(wall: usize, offset: f32) -> Self {
+        Self {
+            wall,
+            offset,
+            width: 0.9,
+            height: 2.1,
+            sill_height: 0.,
+            kind: OpeningKind::Door {
+                hinge: DoorHinge::Start,
+                swing: DoorSwing::Inward,
+            },
+        }
+    }
+
+    // Start and end of the opening measured along its wall
+    pub fn span(&self) -> (f32, f32) {
+        (self.offset - self.width / 2., self.offset + self.width / 2.)
+    }
+
+    pub fn swing_arc(&self, room: &Room) -> Option<SwingArc> {
+        let OpeningKind::Door { hinge, swing } = self.kind else {
+            return None;
+        };
+        let (start, end) = room.walls().nth(self.wall)?;
+        let along = (end - start).normalize_or_zero();
+        let (span_start, span_end) = self.span();
+        let (hinge, closed) = match hinge {
+            DoorHinge::Start => (start + along * span_start, along),
+            DoorHinge::End => (start + along * span_end, -along),
+        };
+        let inward = room.inward_normal(start, end);
+        Some(SwingArc {
+            hinge,
+            radius: self.width,
+            closed,
+            open: match swing {
+                DoorSwing::Inward => inward,
+                DoorSwing::Outward => -inward,
+            },
+        })
+    }
+}
+
+// The quarter circle on the floor swept by a door leaf, between its closed
+// direction along the wall and its fully open direction
+#[derive(Debug, Clone, Copy)]
+pub struct SwingArc {
+    pub hinge: Vec2,
+    pub radius: f32,
+    pub closed: Vec2,
+    pub open: Vec2,
+}
+
+impl SwingArc {
+    // Whether a footprint on the floor reaches into the swing. The arc is
+    // taken as its outline polygon, and as both shapes are convex they
+    // overlap unless the line along one of their edges separates them.
+    pub fn overlaps(&self, (min, max): (Vec2, Vec2)) -> bool {
+        let mut arc = self.outline(SWING_SEGMENTS);
+        arc.pop();
+        let footprint = [min, Vec2::new(max.x, min.y), max, Vec2::new(min.x, max.y)];
+        let edges = |points: &[Vec2]| {
+            (0..points.len())
+                .map(|i| points[(i + 1) % points.len()] - points[i])
+                .collect::<Vec<_>>()
+        };
+        let shadow = |points: &[Vec2], axis: Vec2| {
+            points
+                .iter()
+                .map(|point| point.dot(axis))
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |(low, high), value| {
+                    (low.min(value), high.max(value))
+                })
+        };
+        edges(&arc)
+            .into_iter()
+            .chain(edges(&footprint))
+            .filter_map(|edge| edge.perp().try_normalize())
+            .all(|axis| {
+                let (arc_min, arc_max) = shadow(&arc, axis);
+                let (min, max) = shadow(&footprint, axis);
+                arc_min <= max && min <= arc_max
+            })
+    }
+
+    pub fn outline(&self, segments: usize) -> Vec<Vec2> {
+        let mut points = vec![self.hinge];
+        points.extend((0..=segments).map(|i| {
+            let t = i as f32 / segments as f32 * std::f32::consts::FRAC_PI_2;
+            self.hinge + (self.closed * t.cos() + self.open * t.sin()) * self.radius
+        }));
+        points.push(self.hinge);
+        points
+    }
+}
+
+// The opening under the cursor
+fn opening_at(room: &Room, hit: &WallHit) -> Option<usize> {
+    room.openings.iter().position(|opening| {
+        let (start, end) = opening.span();
+        opening.wall == hit.wall
+            && (start..=end).contains(&hit.offset)
+            && (opening.sill_height..=opening.sill_height + opening.height).contains(&hit.height)
+    })
+}
+
+// Why the opening can't go on its wall, ignoring the opening at `index`
+fn misfit(room: &Room, opening: &WallOpening, index: Option<usize>) -> Option<&'static str> {
+    let (start, end) = opening.span();
+    let length = room
+        .walls()
+        .nth(opening.wall)
+        .map_or(0., |(from, to)| from.distance(to));
+    if opening.width < MIN_OPENING_SIZE || opening.height < MIN_OPENING_SIZE {
+        Some("Openings can't be that small")
+    } else if start < 0. || end > length {
+        Some("Openings have to fit on their wall")
+    } else if opening.sill_height < 0. || opening.sill_height + opening.height > room.wall_height {
+        Some("Openings can't reach past the floor or the top of the wall")
+    } else if room
+        .openings
+        .iter()
+        .enumerate()
+        .any(|(other_index, other)| {
+            let (other_start, other_end) = other.span();
+            Some(other_index) != index
+                && other.wall == opening.wall
+                && start < other_end
+                && other_start < end
+        })
+    {
+        Some("Openings on the same wall can't overlap")
+    } else {
+        None
+    }
+}
+
+// Shift+W adds a window and Shift+D a door on the wall under the cursor,
+// Shift+Delete removes the opening under the cursor. Shift with Left and
+// Right change its width, Up and Down its height, Page Up and Page Down a
+// window's sill height and Tab steps a door through its hinge sides and swings.
+fn edit_openings(
+    keys: Res<Input<KeyCode>>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    camera: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+    hidden: Res<HiddenWalls>,
+    units: Res<Units>,
+    mut room: ResMut<Room>,
+) {
+    if !keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight])
+        || !keys.any_just_pressed(
+            [KeyCode::W, KeyCode::D, KeyCode::Delete]
+                .into_iter()
+                .chain(ADJUST_KEYS),
+        )
+    {
+        return;
+    }
+    let (Ok(window), Ok((camera, camera_transform))) = (window.get_single(), camera.get_single())
+    else {
+        return;
+    };
+    if window.cursor.grab_mode != CursorGrabMode::None {
+        return;
+    }
+    let Some(ray) = window
+        .cursor_position()
+        .and_then(|position| camera.viewport_to_world(camera_transform, position))
+    else {
+        return;
+    };
+    let Some(hit) = room.wall_hit_except(ray, &hidden.0) else {
+        return;
+    };
+    if keys.any_just_pressed(ADJUST_KEYS) {
+        if let Some(index) = opening_at(&room, &hit) {
+            adjust_opening(&keys, &units, &mut room, index);
+        }
+        return;
+    }
+    if keys.just_pressed(KeyCode::Delete) {
+        if let Some(index) = opening_at(&room, &hit) {
+            room.openings.remove(index);
+        }
+        return;
+    }
+    let opening = if keys.just_pressed(KeyCode::W) {
+        WallOpening::window(hit.wall, hit.offset)
+    } else {
+        WallOpening::door(hit.wall, hit.offset)
+    };
+    if let Some(reason) = misfit(&room, &opening, None) {
+        warn!("{reason}");
+        return;
+    }
+    room.openings.push(opening);
+}
+
+fn adjust_opening(keys: &Input<KeyCode>, units: &Units, room: &mut Room, index: usize) {
+    let mut opening = room.openings[index].clone();
+    let step = |key: KeyCode, sign: f32| {
+        if keys.just_pressed(key) {
+            sign * OPENING_STEP
+        } else {
+            0.
+        }
+    };
+    opening.width += step(KeyCode::Right, 1.) + step(KeyCode::Left, -1.);
+    opening.height += step(KeyCode::Up, 1.) + step(KeyCode::Down, -1.);
+    let raise = step(KeyCode::PageUp, 1.) + step(KeyCode::PageDown, -1.);
+    match &mut opening.kind {
+        OpeningKind::Window => opening.sill_height += raise,
+        OpeningKind::Door { .. } if raise != 0. => {
+            warn!("Doors stand on the floor");
+            return;
+        }
+        OpeningKind::Door { hinge, swing } => {
+            if keys.just_pressed(KeyCode::Tab) {
+                (*hinge, *swing) = match (*hinge, *swing) {
+                    (DoorHinge::Start, DoorSwing::Inward) => (DoorHinge::End, DoorSwing::Inward),
+                    (DoorHinge::End, DoorSwing::Inward) => (DoorHinge::Start, DoorSwing::Outward),
+                    (DoorHinge::Start, DoorSwing::Outward) => (DoorHinge::End, DoorSwing::Outward),
+                    (DoorHinge::End, DoorSwing::Outward) => (DoorHinge::Start, DoorSwing::Inward),
+                };
+            }
+        }
+    }
+    if let Some(reason) = misfit(room, &opening, Some(index)) {
+        warn!("{reason}");
+        return;
+    }
+    match opening.kind {
+        OpeningKind::Door { hinge, swing } if keys.just_pressed(KeyCode::Tab) => {
+            info!("Door hinged at its {hinge:?} side, opening {swing:?}");
+        }
+        _ => info!(
+            "Opening {} wide and {} high, {} above the floor",
+            units.format(opening.width),
+            units.format(opening.height),
+            units.format(opening.sill_height)
+        ),
+    }
+    room.openings[index] = opening;
+}
+
+// Swings with a model's footprint in them are drawn red
+fn draw_door_swings(
+    room: Res<Room>,
+    models: Query<(Entity, &GlobalTransform), With<PlacedModel>>,
+    children: Query<&Children>,
+    meshes: Query<(&Aabb, &GlobalTransform)>,
+    mut gizmos: Gizmos,
+) {
+    let footprints: Vec<(Vec2, Vec2)> = models
+        .iter()
+        .filter_map(|(entity, global)| {
+            let bounds = model_bounds(entity, global, &children, &meshes)?;
+            let (min, max) = world_bounds(bounds, &global.compute_transform());
+            Some((min.xz(), max.xz()))
+        })
+        .collect();
+    for arc in room.door_swings() {
+        let blocked = footprints.iter().any(|footprint| arc.overlaps(*footprint));
+        gizmos.linestrip(
+            arc.outline(SWING_SEGMENTS)
+                .into_iter()
+                .map(|point| Vec3::new(point.x, 0.01, point.y)),
+            if blocked { Color::RED } else { Color::ORANGE },
+        );
+    }
+}
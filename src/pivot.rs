@@ -0,0 +1,73 @@
+use bevy::{gltf::Gltf, prelude::*, render::primitives::Aabb};
+
+use crate::{metadata::Metadata, model_bounds};
+
+// Scans come with arbitrary origins, so each model's scene is spawned as a
+// child of the entity that gets moved around and offset so that entity's
+// origin ends up at the bottom centre of the scene's bounds.
+pub struct PivotPlugin;
+
+impl Plugin for PivotPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, correct_pivots);
+    }
+}
+
+// The scene inside a placed model or placement preview. It also carries the
+// model's base scale, so a placed model's own scale of one is its true size.
+#[derive(Component)]
+pub struct ModelPivot(pub Handle<Gltf>);
+
+// A scene whose offset is worked out once it has spawned and its meshes have
+// bounds. Added again when the scene is reloaded.
+#[derive(Component)]
+pub struct PivotPending;
+
+// A model placed by a layout saved before pivots were corrected. Its transform
+// is adjusted so it stays where it was.
+#[derive(Component)]
+pub struct LegacyOrigin;
+
+pub fn model_scene_bundle(model: Handle<Gltf>, scene: Handle<Scene>) -> impl Bundle {
+    (
+        SceneBundle { scene, ..default() },
+        ModelPivot(model),
+        PivotPending,
+    )
+}
+
+#[allow(clippy::type_complexity)]
+fn correct_pivots(
+    mut commands: Commands,
+    mut pivots: Query<
+        (
+            Entity,
+            &ModelPivot,
+            &Parent,
+            &mut Transform,
+            &GlobalTransform,
+        ),
+        With<PivotPending>,
+    >,
+    mut legacy_models: Query<&mut Transform, (With<LegacyOrigin>, Without<ModelPivot>)>,
+    children: Query<&Children>,
+    meshes: Query<(&Aabb, &GlobalTransform)>,
+    mut metadata: Metadata,
+) {
+    for (entity, pivot, parent, mut transform, global) in &mut pivots {
+        // In the scene's own units, whatever scale the pivot has now
+        let Some((min, max)) = model_bounds(entity, global, &children, &meshes) else {
+            continue;
+        };
+        let base_scale = metadata.get(&pivot.0).base_scale;
+        let base = Vec3::new((min.x + max.x) / 2., min.y, (min.z + max.z) / 2.) * base_scale;
+        transform.translation = -base;
+        transform.scale = Vec3::splat(base_scale);
+        commands.entity(entity).remove::<PivotPending>();
+        if let Ok(mut model) = legacy_models.get_mut(parent.get()) {
+            let offset = model.rotation * (model.scale * base);
+            model.translation += offset;
+            commands.entity(parent.get()).remove::<LegacyOrigin>();
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use bevy::{gltf::Gltf, prelude::*, render::primitives::Aabb, window::PrimaryWindow};
+
+use crate::{
+    history::{EditCommand, History, ModelSnapshot},
+    model_bounds, model_scene,
+    pivot::model_scene_bundle,
+    room::Room,
+    spawn_placed_model,
+    surface::{SettleOnSurface, Surfaces},
+    RIGHT_SIDEBAR_WIDTH,
+};
+
+pub struct PlacementPlugin;
+
+impl Plugin for PlacementPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (update_ghost, drop_sidebar_model)
+                .chain()
+                .run_if(resource_exists::<SidebarDrag>()),
+        );
+    }
+}
+
+// A model list entry that is being pressed or dragged out of the sidebar
+#[derive(Resource)]
+pub struct SidebarDrag {
+    model: Handle<Gltf>,
+    entered_viewport: bool,
+}
+
+impl SidebarDrag {
+    pub fn new(model: Handle<Gltf>) -> Self {
+        Self {
+            model,
+            entered_viewport: false,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct GhostPreview;
+
+pub fn floor_hit(
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+    position: Vec2,
+) -> Option<Vec3> {
+    let ray = camera.viewport_to_world(camera_transform, position)?;
+    let distance = ray.intersect_plane(Vec3::ZERO, Vec3::Y)?;
+    Some(ray.get_point(distance))
+}
+
+// New models face the same way as the camera
+fn placement_rotation(camera_transform: &GlobalTransform) -> Quat {
+    let (yaw, _, _) = camera_transform
+        .compute_transform()
+        .rotation
+        .to_euler(EulerRot::YXZ);
+    Quat::from_rotation_y(yaw)
+}
+
+fn cursor_target(
+    window: &Window,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+    room: &Room,
+) -> Option<Vec3> {
+    let position = window.cursor_position()?;
+    if position.x >= window.width() - RIGHT_SIDEBAR_WIDTH {
+        return None;
+    }
+    Some(room.clamp(floor_hit(camera, camera_transform, position)?))
+}
+
+// Where a clicked (rather than dragged) model goes: the floor under the
+// middle of the 3d view, or under the camera when looking above the horizon
+fn centre_target(
+    window: &Window,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+    room: &Room,
+) -> Vec3 {
+    let centre = Vec2::new(
+        (window.width() - RIGHT_SIDEBAR_WIDTH) / 2.,
+        window.height() / 2.,
+    );
+    let target = floor_hit(camera, camera_transform, centre)
+        .unwrap_or(camera_transform.translation() * Vec3::new(1., 0., 1.));
+    room.clamp(target)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_ghost(
+    mut commands: Commands,
+    mut drag: ResMut<SidebarDrag>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    camera: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+    gltf_assets: Res<Assets<Gltf>>,
+    room: Res<Room>,
+    mut ghosts: Query<
+        (Entity, &mut Transform, &GlobalTransform, &mut Visibility),
+        With<GhostPreview>,
+    >,
+    children: Query<&Children>,
+    meshes: Query<(&Aabb, &GlobalTransform)>,
+    surfaces: Surfaces,
+) {
+    let (Ok(window), Ok((camera, camera_transform))) = (window.get_single(), camera.get_single())
+    else {
+        return;
+    };
+    let target = cursor_target(window, camera, camera_transform, &room);
+    if target.is_some() {
+        drag.entered_viewport = true;
+    }
+    match (ghosts.get_single_mut(), target) {
+        (Ok((ghost, mut transform, global, mut visibility)), Some(target)) => {
+            transform.translation = target;
+            transform.rotation = placement_rotation(camera_transform);
+            if let Some(bounds) = model_bounds(ghost, global, &children, &meshes) {
+                transform.translation.y = surfaces.resting_height(&transform, bounds, &[]);
+            }
+            *visibility = Visibility::Inherited;
+        }
+        (Ok((.., mut visibility)), None) => {
+            *visibility = Visibility::Hidden;
+        }
+        (Err(_), Some(target)) => {
+            let Some(scene) = model_scene(&gltf_assets, &drag.model) else {
+                return;
+            };
+            commands
+                .spawn((
+                    SpatialBundle::from_transform(
+                        Transform::from_translation(target)
+                            .with_rotation(placement_rotation(camera_transform)),
+                    ),
+                    GhostPreview,
+                ))
+                .with_children(|parent| {
+                    parent.spawn(model_scene_bundle(drag.model.clone(), scene));
+                });
+        }
+        (Err(_), None) => {}
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn drop_sidebar_model(
+    mut commands: Commands,
+    mouse: Res<Input<MouseButton>>,
+    drag: Res<SidebarDrag>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    camera: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+    gltf_assets: Res<Assets<Gltf>>,
+    room: Res<Room>,
+    ghosts: Query<Entity, With<GhostPreview>>,
+    mut history: ResMut<History>,
+) {
+    if mouse.pressed(MouseButton::Left) {
+        return;
+    }
+    commands.remove_resource::<SidebarDrag>();
+    for ghost in &ghosts {
+        commands.entity(ghost).despawn_recursive();
+    }
+    let (Ok(window), Ok((camera, camera_transform))) = (window.get_single(), camera.get_single())
+    else {
+        error!("Unable to find window or camera while spawning model");
+        return;
+    };
+    let translation = match cursor_target(window, camera, camera_transform, &room) {
+        Some(target) => target,
+        None if !drag.entered_viewport => centre_target(window, camera, camera_transform, &room),
+        // Dragged back onto the sidebar, which cancels the placement
+        None => return,
+    };
+    let transform = Transform::from_translation(translation)
+        .with_rotation(placement_rotation(camera_transform));
+    if let Some(entity) =
+        spawn_placed_model(&mut commands, &gltf_assets, drag.model.clone(), transform)
+    {
+        commands.entity(entity).insert(SettleOnSurface);
+        history.push(EditCommand::Spawn {
+            entity,
+            snapshot: ModelSnapshot {
+                model: drag.model.clone(),
+                transform,
+            },
+        });
+    }
+}
@@ -0,0 +1,182 @@
+use bevy::{gltf::Gltf, prelude::*};
+
+use crate::{
+    library::{library_path, ModelLibrary},
+    loading::{asset_dir, find_models, LoadReport, ModelLoads, MODEL_DIR},
+    model_scene,
+    pivot::{ModelPivot, PivotPending},
+    AabbMeshMap, ListItemModel, LoadedModelList, PlacedModel,
+};
+
+// Keeps the model list and placed instances in step with the model files
+// while the file watcher reloads them
+pub struct ReloadPlugin;
+
+impl Plugin for ReloadPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ScanTimer(Timer::from_seconds(1., TimerMode::Repeating)))
+            .add_systems(
+                Update,
+                (
+                    refresh_modified_models,
+                    scan_model_files,
+                    draw_missing_models,
+                ),
+            );
+    }
+}
+
+#[derive(Resource)]
+struct ScanTimer(Timer);
+
+// A placed model whose file has been removed. It keeps showing the last
+// loaded version and is restored if the file comes back.
+#[derive(Component)]
+pub struct MissingModel;
+
+// Scenes aren't respawned when their asset changes, so each instance gets its
+// scene handle set again, which respawns it inside the same placed model
+fn refresh_modified_models(
+    mut commands: Commands,
+    mut gltf_events: EventReader<AssetEvent<Gltf>>,
+    gltf_assets: Res<Assets<Gltf>>,
+    model_list: Res<LoadedModelList>,
+    mut aabb_mesh_map: ResMut<AabbMeshMap>,
+    placed_models: Query<&PlacedModel>,
+    mut scenes: Query<(Entity, &Parent, &mut Handle<Scene>), With<ModelPivot>>,
+) {
+    let modified: Vec<AssetId<Gltf>> = gltf_events
+        .read()
+        .filter_map(|event| match event {
+            AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect();
+    if modified.is_empty() {
+        return;
+    }
+    for id in &modified {
+        let Some(path) = model_list
+            .0
+            .iter()
+            .find(|handle| handle.id() == *id)
+            .and_then(|handle| handle.path())
+        else {
+            continue;
+        };
+        info!("Reloaded {path}");
+        // The picking boxes are cached per mesh handle, which a reload keeps
+        aabb_mesh_map.0.retain(|mesh, _| {
+            !mesh
+                .path()
+                .is_some_and(|mesh_path| mesh_path.without_label() == path.without_label())
+        });
+    }
+    for (entity, parent, mut scene) in &mut scenes {
+        let Ok(model) = placed_models.get(parent.get()) else {
+            continue;
+        };
+        if !modified.contains(&model.0.id()) {
+            continue;
+        }
+        if let Some(new_scene) = model_scene(&gltf_assets, &model.0) {
+            *scene = new_scene;
+            // The new version can have a different origin
+            commands.entity(entity).insert(PivotPending);
+        }
+    }
+}
+
+// The file watcher only reloads files the asset server already knows about,
+// so the model folders are rescanned to notice added and removed files
+#[allow(clippy::too_many_arguments)]
+fn scan_model_files(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut timer: ResMut<ScanTimer>,
+    asset_server: Res<AssetServer>,
+    library: Res<ModelLibrary>,
+    mut loads: ResMut<ModelLoads>,
+    mut report: ResMut<LoadReport>,
+    mut model_list: ResMut<LoadedModelList>,
+    list_items: Query<(&ListItemModel, &Parent)>,
+    placed_models: Query<(Entity, &PlacedModel, Has<MissingModel>)>,
+) {
+    if !timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+    let assets = asset_dir();
+    let mut files = Vec::new();
+    find_models(&assets, &assets.join(MODEL_DIR), &mut files);
+    files.extend(
+        library
+            .models()
+            .iter()
+            .map(|relative| library_path(relative)),
+    );
+    let has_file = |handle: &Handle<Gltf>| {
+        handle
+            .path()
+            .is_none_or(|path| files.contains(&path.to_string()))
+    };
+    let removed: Vec<Handle<Gltf>> = model_list
+        .0
+        .iter()
+        .filter(|handle| !has_file(handle))
+        .cloned()
+        .collect();
+    for handle in &removed {
+        warn!(
+            "{} was removed",
+            handle
+                .path()
+                .map(|path| path.to_string())
+                .unwrap_or_default()
+        );
+    }
+    model_list.0.retain(|handle| !removed.contains(handle));
+    for (item, button) in &list_items {
+        if removed.contains(&item.0) {
+            commands.entity(button.get()).despawn_recursive();
+        }
+    }
+    for (entity, model, missing) in &placed_models {
+        if !missing && removed.contains(&model.0) {
+            commands.entity(entity).insert(MissingModel);
+        }
+    }
+    if report
+        .failures
+        .iter()
+        .any(|failure| !files.contains(&failure.path))
+    {
+        report
+            .failures
+            .retain(|failure| files.contains(&failure.path));
+    }
+    for path in files {
+        if model_list.find_by_path(&path).is_some()
+            || loads.is_loading(&path)
+            || report.has_failed(&path)
+        {
+            continue;
+        }
+        // A file that comes back still has its old handle and contents
+        let restored = placed_models.iter().any(|(_, model, missing)| {
+            missing && model.0.path().is_some_and(|p| p.to_string() == path)
+        });
+        if restored {
+            asset_server.reload(path.clone());
+        }
+        loads.start(&asset_server, path);
+    }
+}
+
+fn draw_missing_models(
+    missing_models: Query<&GlobalTransform, With<MissingModel>>,
+    mut gizmos: Gizmos,
+) {
+    for transform in &missing_models {
+        gizmos.sphere(transform.translation(), Quat::IDENTITY, 0.5, Color::RED);
+    }
+}
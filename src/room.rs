@@ -0,0 +1,449 @@
+use bevy::{
+    pbr::NotShadowCaster,
+    prelude::*,
+    render::{mesh::Indices, render_resource::PrimitiveTopology},
+};
+use bevy_mod_picking::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::openings::{SwingArc, WallOpening};
+
+pub struct RoomPlugin;
+
+impl Plugin for RoomPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Room>()
+            .add_systems(Update, spawn_room.run_if(resource_changed::<Room>()));
+    }
+}
+
+// Room dimensions are in metres, the same unit glTF scans and the world use.
+// The floor outline is a simple polygon on the XZ plane, and wall `i` runs
+// from `outline[i]` to `outline[i + 1]`.
+#[derive(Debug, Resource, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Room {
+    pub outline: Vec<[f32; 2]>,
+    pub wall_height: f32,
+    pub floor_material: RoomMaterial,
+    // One entry per wall; walls without their own entry use the last one
+    pub wall_materials: Vec<RoomMaterial>,
+    #[serde(default)]
+    pub openings: Vec<WallOpening>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomMaterial {
+    pub texture: Option<String>,
+    pub color: [f32; 4],
+}
+
+impl RoomMaterial {
+    pub fn textured(texture: &str) -> Self {
+        Self {
+            texture: Some(texture.to_string()),
+            color: [1., 1., 1., 1.],
+        }
+    }
+
+    fn to_standard_material(&self, asset_server: &AssetServer) -> StandardMaterial {
+        StandardMaterial {
+            base_color: Color::rgba(self.color[0], self.color[1], self.color[2], self.color[3]),
+            base_color_texture: self.texture.as_ref().map(|path| asset_server.load(path)),
+            perceptual_roughness: 0.8,
+            cull_mode: None,
+            ..default()
+        }
+    }
+}
+
+impl Default for RoomMaterial {
+    fn default() -> Self {
+        Self {
+            texture: None,
+            color: [1., 1., 1., 1.],
+        }
+    }
+}
+
+impl Default for Room {
+    fn default() -> Self {
+        Self::rectangle(4., 5., 2.5)
+    }
+}
+
+impl Room {
+    // `width` runs along X and `length` along Z, centred on the origin
+    pub fn rectangle(width: f32, length: f32, height: f32) -> Self {
+        let (x, z) = (width / 2., length / 2.);
+        Self {
+            outline: vec![[-x, -z], [-x, z], [x, z], [x, -z]],
+            wall_height: height,
+            floor_material: RoomMaterial::textured("images/wood.png"),
+            wall_materials: vec![RoomMaterial::textured("images/wallpaper.png")],
+            openings: Vec::new(),
+        }
+    }
+
+    pub fn corners(&self) -> Vec<Vec2> {
+        self.outline.iter().copied().map(Vec2::from).collect()
+    }
+
+    pub fn walls(&self) -> impl Iterator<Item = (Vec2, Vec2)> + '_ {
+        let corners = self.corners();
+        (0..corners.len()).map(move |i| (corners[i], corners[(i + 1) % corners.len()]))
+    }
+
+    pub fn wall_material(&self, wall: usize) -> RoomMaterial {
+        self.wall_materials
+            .get(wall)
+            .or(self.wall_materials.last())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn is_valid(&self) -> bool {
+        self.outline.len() >= 3 && self.wall_height > 0. && signed_area(&self.corners()) != 0.
+    }
+
+    // Unit vector on the XZ plane pointing from a wall into the room
+    pub fn inward_normal(&self, start: Vec2, end: Vec2) -> Vec2 {
+        let direction = (end - start).normalize_or_zero();
+        let left = direction.perp();
+        if signed_area(&self.corners()) > 0. {
+            left
+        } else {
+            -left
+        }
+    }
+
+    pub fn door_swings(&self) -> Vec<SwingArc> {
+        self.openings
+            .iter()
+            .filter_map(|opening| opening.swing_arc(self))
+            .collect()
+    }
+
+    // The closest wall a ray hits from the inside or outside, ignoring openings
+    pub fn wall_hit(&self, ray: Ray) -> Option<WallHit> {
+        self.wall_hit_except(ray, &[])
+    }
+
+    // Like `wall_hit`, passing through the walls in `skip`
+    pub fn wall_hit_except(&self, ray: Ray, skip: &[usize]) -> Option<WallHit> {
+        self.walls()
+            .enumerate()
+            .filter(|(wall, _)| !skip.contains(wall))
+            .filter_map(|(wall, (start, end))| {
+                let normal = self.inward_normal(start, end);
+                let distance = ray.intersect_plane(
+                    Vec3::new(start.x, 0., start.y),
+                    Vec3::new(normal.x, 0., normal.y),
+                )?;
+                let point = ray.get_point(distance);
+                let offset = (point.xz() - start).dot((end - start).normalize_or_zero());
+                let on_wall = (0.0..=start.distance(end)).contains(&offset)
+                    && (0.0..=self.wall_height).contains(&point.y);
+                on_wall.then_some(WallHit {
+                    wall,
+                    offset,
+                    height: point.y,
+                    distance,
+                })
+            })
+            .min_by(|a, b| a.distance.total_cmp(&b.distance))
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        let mut inside = false;
+        for (a, b) in self.walls() {
+            if (a.y > point.y) != (b.y > point.y)
+                && point.x < (b.x - a.x) * (point.y - a.y) / (b.y - a.y) + a.x
+            {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+
+    pub fn closest_wall_point(&self, point: Vec2) -> Option<Vec2> {
+        self.walls()
+            .map(|(a, b)| closest_point_on_segment(point, a, b))
+            .min_by(|p, q| {
+                p.distance_squared(point)
+                    .total_cmp(&q.distance_squared(point))
+            })
+    }
+
+    // Moves a point outside the floor outline back onto it, keeping its height
+    pub fn clamp(&self, point: Vec3) -> Vec3 {
+        let flat = point.xz();
+        if self.contains(flat) {
+            return point;
+        }
+        match self.closest_wall_point(flat) {
+            Some(closest) => Vec3::new(closest.x, point.y, closest.y),
+            None => point,
+        }
+    }
+}
+
+pub struct WallHit {
+    pub wall: usize,
+    // Distance along the wall from its start corner
+    pub offset: f32,
+    pub height: f32,
+    pub distance: f32,
+}
+
+pub fn closest_point_on_segment(point: Vec2, a: Vec2, b: Vec2) -> Vec2 {
+    let segment = b - a;
+    let length_squared = segment.length_squared();
+    if length_squared == 0. {
+        return a;
+    }
+    let t = ((point - a).dot(segment) / length_squared).clamp(0., 1.);
+    a + segment * t
+}
+
+fn signed_area(points: &[Vec2]) -> f32 {
+    (0..points.len())
+        .map(|i| points[i].perp_dot(points[(i + 1) % points.len()]))
+        .sum::<f32>()
+        / 2.
+}
+
+fn point_in_triangle(p: Vec2, a: Vec2, b: Vec2, c: Vec2) -> bool {
+    let d1 = (b - a).perp_dot(p - a);
+    let d2 = (c - b).perp_dot(p - b);
+    let d3 = (a - c).perp_dot(p - c);
+    d1 >= 0. && d2 >= 0. && d3 >= 0.
+}
+
+// Ear clipping, which is plenty for the handful of corners a room has
+fn triangulate(points: &[Vec2]) -> Vec<u32> {
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    if signed_area(points) < 0. {
+        remaining.reverse();
+    }
+    let mut indices = Vec::new();
+    while remaining.len() > 3 {
+        let count = remaining.len();
+        let ear = (0..count).find(|&i| {
+            let prev = remaining[(i + count - 1) % count];
+            let current = remaining[i];
+            let next = remaining[(i + 1) % count];
+            let (a, b, c) = (points[prev], points[current], points[next]);
+            if (b - a).perp_dot(c - b) <= 0. {
+                return false;
+            }
+            !remaining.iter().any(|&j| {
+                j != prev && j != current && j != next && point_in_triangle(points[j], a, b, c)
+            })
+        });
+        let Some(ear) = ear else {
+            warn!("Room outline is self-intersecting, the floor may have holes");
+            break;
+        };
+        let count = remaining.len();
+        indices.extend([
+            remaining[(ear + count - 1) % count] as u32,
+            remaining[ear] as u32,
+            remaining[(ear + 1) % count] as u32,
+        ]);
+        remaining.remove(ear);
+    }
+    if remaining.len() == 3 {
+        indices.extend(remaining.iter().map(|&i| i as u32));
+    }
+    indices
+}
+
+fn floor_mesh(room: &Room) -> Mesh {
+    let corners = room.corners();
+    let min = corners.iter().copied().fold(Vec2::MAX, Vec2::min);
+    let size =
+        (corners.iter().copied().fold(Vec2::MIN, Vec2::max) - min).max(Vec2::splat(f32::EPSILON));
+    let positions: Vec<[f32; 3]> = corners.iter().map(|c| [c.x, 0., c.y]).collect();
+    let uvs: Vec<[f32; 2]> = corners
+        .iter()
+        .map(|c| ((*c - min) / size).to_array())
+        .collect();
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0., 1., 0.]; positions.len()]);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.set_indices(Some(Indices::U32(triangulate(&corners))));
+    mesh
+}
+
+// The wall is split into rectangles around its openings, each given as
+// (start, end) along the wall and (bottom, top) in height
+fn wall_rects(length: f32, height: f32, openings: &[&WallOpening]) -> Vec<[f32; 4]> {
+    let mut holes: Vec<[f32; 4]> = openings
+        .iter()
+        .map(|opening| {
+            let (start, end) = opening.span();
+            [
+                start.max(0.),
+                end.min(length),
+                opening.sill_height.max(0.),
+                (opening.sill_height + opening.height).min(height),
+            ]
+        })
+        .filter(|[start, end, bottom, top]| start < end && bottom < top)
+        .collect();
+    holes.sort_by(|a, b| a[0].total_cmp(&b[0]));
+    let mut rects = Vec::new();
+    let mut cursor = 0.;
+    for [start, end, bottom, top] in holes {
+        if start < cursor {
+            warn!("Skipping an opening that overlaps another on the same wall");
+            continue;
+        }
+        if start > cursor {
+            rects.push([cursor, start, 0., height]);
+        }
+        if bottom > 0. {
+            rects.push([start, end, 0., bottom]);
+        }
+        if top < height {
+            rects.push([start, end, top, height]);
+        }
+        cursor = end;
+    }
+    if cursor < length {
+        rects.push([cursor, length, 0., height]);
+    }
+    rects
+}
+
+fn wall_mesh(start: Vec2, end: Vec2, height: f32, normal: Vec2, openings: &[&WallOpening]) -> Mesh {
+    let length = start.distance(end);
+    let direction = (end - start).normalize_or_zero();
+    let mut positions = Vec::new();
+    let mut uvs = Vec::new();
+    let mut indices = Vec::new();
+    for [left, right, bottom, top] in wall_rects(length, height, openings) {
+        let first = positions.len() as u32;
+        for (u, v) in [(left, bottom), (right, bottom), (right, top), (left, top)] {
+            let point = start + direction * u;
+            positions.push([point.x, v, point.y]);
+            uvs.push([u / length, 1. - v / height]);
+        }
+        indices.extend([0, 1, 2, 0, 2, 3].map(|i| first + i));
+    }
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(
+        Mesh::ATTRIBUTE_NORMAL,
+        vec![[normal.x, 0., normal.y]; positions.len()],
+    );
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+#[derive(Component)]
+pub struct RoomGeometry;
+
+// The index of the wall a piece of room geometry is
+#[derive(Component)]
+pub struct RoomWall(pub usize);
+
+fn spawn_room(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    asset_server: Res<AssetServer>,
+    room: Res<Room>,
+    old_geometry: Query<Entity, With<RoomGeometry>>,
+) {
+    if !room.is_valid() {
+        error!("Room needs at least three corners and a positive wall height");
+        return;
+    }
+    for entity in &old_geometry {
+        commands.entity(entity).despawn_recursive();
+    }
+    // Floor
+    commands.spawn((
+        PbrBundle {
+            mesh: meshes.add(floor_mesh(&room)),
+            material: materials.add(room.floor_material.to_standard_material(&asset_server)),
+            ..default()
+        },
+        Pickable::IGNORE,
+        NotShadowCaster,
+        RoomGeometry,
+    ));
+    for (index, (start, end)) in room.walls().enumerate() {
+        let normal = room.inward_normal(start, end);
+        let openings: Vec<&WallOpening> = room
+            .openings
+            .iter()
+            .filter(|opening| opening.wall == index)
+            .collect();
+        commands.spawn((
+            PbrBundle {
+                mesh: meshes.add(wall_mesh(start, end, room.wall_height, normal, &openings)),
+                material: materials.add(
+                    room.wall_material(index)
+                        .to_standard_material(&asset_server),
+                ),
+                ..default()
+            },
+            Pickable::IGNORE,
+            NotShadowCaster,
+            RoomGeometry,
+            RoomWall(index),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(points: &[Vec2], indices: &[u32]) -> f32 {
+        indices
+            .chunks(3)
+            .map(|triangle| {
+                let [a, b, c] = [0, 1, 2].map(|i| points[triangle[i] as usize]);
+                (b - a).perp_dot(c - a) / 2.
+            })
+            .sum()
+    }
+
+    #[test]
+    fn triangulates_a_rectangle() {
+        let corners = Room::rectangle(4., 5., 2.5).corners();
+        let indices = triangulate(&corners);
+        assert_eq!(indices.len(), 6);
+        assert!((area(&corners, &indices).abs() - 20.).abs() < 1e-5);
+    }
+
+    #[test]
+    fn triangulates_an_l_shape_either_way_round() {
+        let mut corners = vec![
+            Vec2::new(0., 0.),
+            Vec2::new(4., 0.),
+            Vec2::new(4., 2.),
+            Vec2::new(2., 2.),
+            Vec2::new(2., 4.),
+            Vec2::new(0., 4.),
+        ];
+        for _ in 0..2 {
+            let indices = triangulate(&corners);
+            assert_eq!(indices.len(), 12);
+            // Every triangle winds the same way and together they cover the
+            // floor exactly once
+            let windings: Vec<f32> = indices
+                .chunks(3)
+                .map(|triangle| area(&corners, triangle).signum())
+                .collect();
+            assert!(windings.iter().all(|winding| *winding == windings[0]));
+            assert!((area(&corners, &indices).abs() - 12.).abs() < 1e-5);
+            corners.reverse();
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use bevy::{
+    prelude::*,
+    window::{CursorGrabMode, PrimaryWindow},
+};
+use bevy_mod_picking::prelude::*;
+
+use crate::{placed_model_root, LoadingState, PlacedModel, RIGHT_SIDEBAR_WIDTH};
+
+// Dragging less than this many pixels on empty space counts as a click
+const MARQUEE_THRESHOLD: f32 = 4.;
+
+// Replaces bevy_mod_picking's per-mesh selection with a set of placed models.
+// The picking `PickSelection` of each mesh is still kept in step so the
+// selected highlight keeps working.
+pub struct ModelSelectionPlugin;
+
+impl Plugin for ModelSelectionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Selection>()
+            .add_systems(Startup, spawn_marquee)
+            .add_systems(
+                Update,
+                (
+                    prune_selection,
+                    (pointer_selection, marquee_selection, keyboard_selection)
+                        .run_if(in_state(LoadingState::Loaded)),
+                    sync_pick_selection,
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Selection {
+    models: Vec<Entity>,
+}
+
+impl Selection {
+    pub fn models(&self) -> &[Entity] {
+        &self.models
+    }
+
+    pub fn contains(&self, model: Entity) -> bool {
+        self.models.contains(&model)
+    }
+
+    // The models a gesture on `model` applies to: the whole selection if it is
+    // part of it, otherwise just itself
+    pub fn group(&self, model: Entity) -> Vec<Entity> {
+        if self.contains(model) {
+            self.models.clone()
+        } else {
+            vec![model]
+        }
+    }
+
+    pub fn set(&mut self, models: Vec<Entity>) {
+        self.models = models;
+    }
+
+    pub fn add(&mut self, model: Entity) {
+        if !self.contains(model) {
+            self.models.push(model);
+        }
+    }
+
+    pub fn toggle(&mut self, model: Entity) {
+        if self.contains(model) {
+            self.models.retain(|selected| *selected != model);
+        } else {
+            self.models.push(model);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.models.clear();
+    }
+}
+
+fn shift_pressed(keys: &Input<KeyCode>) -> bool {
+    keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight])
+}
+
+// Deleted models and the old entities of undone deletes drop out
+fn prune_selection(mut selection: ResMut<Selection>, models: Query<(), With<PlacedModel>>) {
+    if selection
+        .models
+        .iter()
+        .any(|model| !models.contains(*model))
+    {
+        selection.models.retain(|model| models.contains(*model));
+    }
+}
+
+// Pressing an unselected model selects just it, so dragging it doesn't move
+// the rest of the selection. Shift-click toggles a model in and out.
+#[allow(clippy::too_many_arguments)]
+fn pointer_selection(
+    keys: Res<Input<KeyCode>>,
+    mut down_events: EventReader<Pointer<Down>>,
+    mut drag_start_events: EventReader<Pointer<DragStart>>,
+    mut click_events: EventReader<Pointer<Click>>,
+    mut selection: ResMut<Selection>,
+    parents: Query<&Parent>,
+    placed_models: Query<(), With<PlacedModel>>,
+    mut dragged: Local<bool>,
+) {
+    let shift = shift_pressed(&keys);
+    for event in down_events.read() {
+        *dragged = false;
+        let Some(model) = placed_model_root(event.target, &parents, &placed_models) else {
+            continue;
+        };
+        if shift && event.button == PointerButton::Primary {
+            selection.toggle(model);
+        } else if !shift && !selection.contains(model) {
+            selection.set(vec![model]);
+        }
+    }
+    if drag_start_events.read().count() > 0 {
+        *dragged = true;
+    }
+    for event in click_events.read() {
+        if *dragged || shift || event.button != PointerButton::Primary {
+            continue;
+        }
+        // Clicking one model of a selection without dragging narrows it down
+        if let Some(model) = placed_model_root(event.target, &parents, &placed_models) {
+            selection.set(vec![model]);
+        }
+    }
+}
+
+#[derive(Component)]
+struct Marquee;
+
+fn spawn_marquee(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                border: UiRect::all(Val::Px(1.)),
+                ..default()
+            },
+            background_color: Color::rgba(0.3, 0.6, 1., 0.15).into(),
+            border_color: Color::rgb(0.3, 0.6, 1.).into(),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        Pickable::IGNORE,
+        Marquee,
+    ));
+}
+
+// Pressing on empty space and dragging selects every model whose origin ends
+// up inside the rectangle. Shift adds to the selection instead of replacing it.
+#[allow(clippy::too_many_arguments)]
+fn marquee_selection(
+    mouse: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+    mut down_events: EventReader<Pointer<Down>>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    camera: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+    models: Query<(Entity, &GlobalTransform), With<PlacedModel>>,
+    mut marquee: Query<(&mut Style, &mut Visibility), With<Marquee>>,
+    mut selection: ResMut<Selection>,
+    mut start: Local<Option<Vec2>>,
+) {
+    let hit_something = down_events
+        .read()
+        .any(|event| event.button == PointerButton::Primary);
+    let (Ok(window), Ok((camera, camera_transform)), Ok((mut style, mut visibility))) = (
+        window.get_single(),
+        camera.get_single(),
+        marquee.get_single_mut(),
+    ) else {
+        return;
+    };
+    let Some(cursor) = window.cursor_position() else {
+        return;
+    };
+    if mouse.just_pressed(MouseButton::Left)
+        && !hit_something
+        && window.cursor.grab_mode == CursorGrabMode::None
+        && cursor.x < window.width() - RIGHT_SIDEBAR_WIDTH
+    {
+        *start = Some(cursor);
+    }
+    let Some(corner) = *start else {
+        return;
+    };
+    let min = corner.min(cursor);
+    let max = corner.max(cursor);
+    let is_drag = (max - min).max_element() >= MARQUEE_THRESHOLD;
+    if mouse.pressed(MouseButton::Left) {
+        style.left = Val::Px(min.x);
+        style.top = Val::Px(min.y);
+        style.width = Val::Px(max.x - min.x);
+        style.height = Val::Px(max.y - min.y);
+        *visibility = if is_drag {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        return;
+    }
+    *start = None;
+    *visibility = Visibility::Hidden;
+    let shift = shift_pressed(&keys);
+    if !is_drag {
+        // A click on empty space
+        if !shift {
+            selection.clear();
+        }
+        return;
+    }
+    let inside = models
+        .iter()
+        .filter(|(_, transform)| {
+            camera
+                .world_to_viewport(camera_transform, transform.translation())
+                .is_some_and(|point| point.cmpge(min).all() && point.cmple(max).all())
+        })
+        .map(|(entity, _)| entity);
+    if shift {
+        for model in inside {
+            selection.add(model);
+        }
+    } else {
+        selection.set(inside.collect());
+    }
+}
+
+// Ctrl+A selects every placed model and Escape clears the selection
+fn keyboard_selection(
+    keys: Res<Input<KeyCode>>,
+    models: Query<Entity, With<PlacedModel>>,
+    mut selection: ResMut<Selection>,
+) {
+    if keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight])
+        && keys.just_pressed(KeyCode::A)
+    {
+        selection.set(models.iter().collect());
+    }
+    if keys.just_pressed(KeyCode::Escape) {
+        selection.clear();
+    }
+}
+
+fn sync_pick_selection(
+    selection: Res<Selection>,
+    mut meshes: Query<(Entity, &mut PickSelection)>,
+    added: Query<(), Added<PickSelection>>,
+    parents: Query<&Parent>,
+    placed_models: Query<(), With<PlacedModel>>,
+) {
+    if !selection.is_changed() && added.is_empty() {
+        return;
+    }
+    for (entity, mut pick_selection) in &mut meshes {
+        let selected = placed_model_root(entity, &parents, &placed_models)
+            .is_some_and(|model| selection.contains(model));
+        if pick_selection.is_selected != selected {
+            pick_selection.is_selected = selected;
+        }
+    }
+}
@@ -0,0 +1,704 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use bevy::{input::InputSystem, prelude::*};
+use bevy_flycam::{KeyBindings, MovementSettings};
+use bevy_framepace::{FramepaceSettings, Limiter};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    measure::Units, snap::SnapSettings, walk::WalkSettings, HOVERED_BUTTON, NORMAL_BUTTON,
+    PRESSED_BUTTON,
+};
+
+const SENSITIVITY_STEP: f32 = 0.00001;
+const SPEED_STEP: f32 = 0.5;
+const EYE_HEIGHT_STEP: f32 = 0.05;
+const WALK_SPEED_STEP: f32 = 0.1;
+const GRID_STEP: f32 = 0.05;
+const SNAP_ANGLE_STEP: f32 = 5.;
+const WALL_SNAP_STEP: f32 = 0.05;
+// Frame limits the settings panel steps through
+const FRAME_LIMITS: [FrameLimit; 6] = [
+    FrameLimit::Fps(15),
+    FrameLimit::Fps(30),
+    FrameLimit::Fps(60),
+    FrameLimit::Fps(120),
+    FrameLimit::Auto,
+    FrameLimit::Off,
+];
+
+// Keys the app's shortcuts use on their own or as modifiers, which can't be
+// bound to movement
+const RESERVED_KEYS: [KeyCode; 42] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::Y,
+    KeyCode::F1,
+    KeyCode::Escape,
+    KeyCode::Delete,
+    KeyCode::End,
+    KeyCode::Return,
+    KeyCode::Back,
+    KeyCode::Tab,
+    KeyCode::Left,
+    KeyCode::Right,
+    KeyCode::Up,
+    KeyCode::Down,
+    KeyCode::PageUp,
+    KeyCode::PageDown,
+    KeyCode::ShiftLeft,
+    KeyCode::ShiftRight,
+    KeyCode::ControlLeft,
+    KeyCode::ControlRight,
+    KeyCode::AltLeft,
+    KeyCode::AltRight,
+];
+
+// Mouse sensitivity, flying and walking, movement keys, units, snapping and
+// the frame limit, read from settings.ron in the per-user config directory. F1 opens a
+// panel that changes them, which saves the file. Edits to the file are picked
+// up while the app runs.
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        let path = settings_path();
+        let settings = match read_settings(&path) {
+            Ok(settings) => settings.unwrap_or_default(),
+            Err(err) => {
+                warn!(
+                    "Using the default settings, {} is invalid: {err}",
+                    path.display()
+                );
+                Settings::default()
+            }
+        };
+        app.insert_resource(SettingsFile {
+            modified: modified_time(&path),
+            saved: settings.clone(),
+            path,
+        })
+        .insert_resource(settings)
+        .insert_resource(ScanTimer(Timer::from_seconds(1., TimerMode::Repeating)))
+        .init_resource::<Rebinding>()
+        .add_systems(Startup, spawn_settings_panel)
+        // Runs before anything else reads the keys, so the key being bound
+        // doesn't also do what it does elsewhere
+        .add_systems(
+            PreUpdate,
+            (rebind_key, flycam_speed).chain().after(InputSystem),
+        )
+        .add_systems(
+            Update,
+            (
+                reload_settings,
+                toggle_settings_panel,
+                settings_buttons,
+                apply_settings.run_if(resource_changed::<Settings>()),
+                save_settings.run_if(resource_changed::<Settings>()),
+                show_settings,
+            )
+                .chain(),
+        );
+    }
+}
+
+#[derive(Resource, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub sensitivity: f32,
+    // Flycam speed in metres per second
+    pub speed: f32,
+    // Walk mode's camera height above the floor, in metres
+    pub eye_height: f32,
+    // Metres per second
+    pub walk_speed: f32,
+    pub keys: Keys,
+    pub units: Units,
+    // Snapping steps, where zero turns that kind of snapping off. The grid and
+    // wall distance are in metres and the angle in degrees.
+    pub grid: f32,
+    pub snap_angle: f32,
+    pub wall_snap_distance: f32,
+    pub frame_limit: FrameLimit,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            sensitivity: 0.00015,
+            speed: 12.,
+            eye_height: 1.6,
+            walk_speed: 1.4,
+            keys: Keys::default(),
+            units: Units::default(),
+            grid: 0.25,
+            snap_angle: 15.,
+            wall_snap_distance: 0.3,
+            frame_limit: FrameLimit::Fps(30),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Keys {
+    pub forward: KeyCode,
+    pub backward: KeyCode,
+    pub left: KeyCode,
+    pub right: KeyCode,
+    pub ascend: KeyCode,
+    pub descend: KeyCode,
+    pub grab_cursor: KeyCode,
+}
+
+impl Default for Keys {
+    fn default() -> Self {
+        Self {
+            forward: KeyCode::W,
+            backward: KeyCode::S,
+            left: KeyCode::A,
+            right: KeyCode::D,
+            ascend: KeyCode::E,
+            descend: KeyCode::Q,
+            grab_cursor: KeyCode::R,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum FrameLimit {
+    // The display's refresh rate
+    Auto,
+    Fps(u32),
+    Off,
+}
+
+impl FrameLimit {
+    fn limiter(self) -> Limiter {
+        match self {
+            FrameLimit::Auto => Limiter::Auto,
+            FrameLimit::Fps(fps) => {
+                Limiter::Manual(Duration::from_secs_f32(1. / fps.max(1) as f32))
+            }
+            FrameLimit::Off => Limiter::Off,
+        }
+    }
+
+    fn step(self, step: i32) -> FrameLimit {
+        // A limit from the file that isn't in the list steps from 30 FPS
+        let index = FRAME_LIMITS
+            .iter()
+            .position(|limit| *limit == self)
+            .unwrap_or(1) as i32;
+        FRAME_LIMITS[(index + step).clamp(0, FRAME_LIMITS.len() as i32 - 1) as usize]
+    }
+
+    fn name(self) -> String {
+        match self {
+            FrameLimit::Auto => "Display".to_string(),
+            FrameLimit::Fps(fps) => format!("{fps} FPS"),
+            FrameLimit::Off => "Off".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Binding {
+    Forward,
+    Backward,
+    Left,
+    Right,
+    Ascend,
+    Descend,
+    GrabCursor,
+}
+
+impl Binding {
+    const ALL: [Binding; 7] = [
+        Binding::Forward,
+        Binding::Backward,
+        Binding::Left,
+        Binding::Right,
+        Binding::Ascend,
+        Binding::Descend,
+        Binding::GrabCursor,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Binding::Forward => "Forward",
+            Binding::Backward => "Back",
+            Binding::Left => "Left",
+            Binding::Right => "Right",
+            Binding::Ascend => "Up",
+            Binding::Descend => "Down",
+            Binding::GrabCursor => "Grab cursor",
+        }
+    }
+
+    fn key(self, keys: &mut Keys) -> &mut KeyCode {
+        match self {
+            Binding::Forward => &mut keys.forward,
+            Binding::Backward => &mut keys.backward,
+            Binding::Left => &mut keys.left,
+            Binding::Right => &mut keys.right,
+            Binding::Ascend => &mut keys.ascend,
+            Binding::Descend => &mut keys.descend,
+            Binding::GrabCursor => &mut keys.grab_cursor,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Setting {
+    Sensitivity,
+    Speed,
+    EyeHeight,
+    WalkSpeed,
+    Units,
+    Grid,
+    SnapAngle,
+    WallSnap,
+    FrameLimit,
+    Key(Binding),
+}
+
+// Where the settings are stored and what was last read from or written to it
+#[derive(Resource)]
+struct SettingsFile {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    saved: Settings,
+}
+
+#[derive(Resource)]
+struct ScanTimer(Timer);
+
+// The key binding waiting for a key press
+#[derive(Resource, Default)]
+struct Rebinding(Option<Binding>);
+
+#[derive(Component)]
+struct SettingsPanel;
+
+// Changes a setting by `step` when clicked, or waits for a new key
+#[derive(Component)]
+struct SettingButton {
+    setting: Setting,
+    step: i32,
+}
+
+#[derive(Component)]
+struct SettingValue(Setting);
+
+fn settings_path() -> PathBuf {
+    directories::ProjectDirs::from("", "", "Deco")
+        .map(|dirs| dirs.config_dir().join("settings.ron"))
+        .unwrap_or_else(|| PathBuf::from("settings.ron"))
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+// None when there is no settings file yet
+fn read_settings(path: &Path) -> Result<Option<Settings>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    ron::from_str(&text)
+        .map(Some)
+        .map_err(|err| err.to_string())
+}
+
+fn write_settings(path: &Path, settings: &Settings) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+    }
+    let text = ron::ser::to_string_pretty(settings, ron::ser::PrettyConfig::default())
+        .map_err(|err| err.to_string())?;
+    fs::write(path, text).map_err(|err| err.to_string())
+}
+
+fn reload_settings(
+    time: Res<Time>,
+    mut timer: ResMut<ScanTimer>,
+    mut file: ResMut<SettingsFile>,
+    mut settings: ResMut<Settings>,
+) {
+    if !timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+    let modified = modified_time(&file.path);
+    if modified == file.modified {
+        return;
+    }
+    file.modified = modified;
+    match read_settings(&file.path) {
+        Ok(Some(loaded)) => {
+            info!("Reloaded the settings from {}", file.path.display());
+            file.saved = loaded.clone();
+            *settings = loaded;
+        }
+        Ok(None) => {}
+        Err(err) => warn!(
+            "Keeping the current settings, {} is invalid: {err}",
+            file.path.display()
+        ),
+    }
+}
+
+fn apply_settings(
+    settings: Res<Settings>,
+    mut movement: ResMut<MovementSettings>,
+    mut key_bindings: ResMut<KeyBindings>,
+    mut framepace: ResMut<FramepaceSettings>,
+    mut units: ResMut<Units>,
+    mut walk: ResMut<WalkSettings>,
+    mut snap: ResMut<SnapSettings>,
+) {
+    movement.sensitivity = settings.sensitivity;
+    *key_bindings = KeyBindings {
+        move_forward: settings.keys.forward,
+        move_backward: settings.keys.backward,
+        move_left: settings.keys.left,
+        move_right: settings.keys.right,
+        move_ascend: settings.keys.ascend,
+        move_descend: settings.keys.descend,
+        toggle_grab_cursor: settings.keys.grab_cursor,
+    };
+    framepace.limiter = settings.frame_limit.limiter();
+    *units = settings.units;
+    walk.eye_height = settings.eye_height;
+    walk.speed = settings.walk_speed;
+    snap.grid = settings.grid;
+    snap.angle = settings.snap_angle.to_radians();
+    snap.wall_distance = settings.wall_snap_distance;
+}
+
+// Ctrl+S, Ctrl+D and Ctrl+A share keys with moving, so the flycam stands
+// still while Ctrl is held
+fn flycam_speed(
+    keys: Res<Input<KeyCode>>,
+    settings: Res<Settings>,
+    mut movement: ResMut<MovementSettings>,
+) {
+    let speed = if keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        0.
+    } else {
+        settings.speed
+    };
+    if movement.speed != speed {
+        movement.speed = speed;
+    }
+}
+
+// Only writes settings changed in the app, not ones just read from the file
+fn save_settings(settings: Res<Settings>, mut file: ResMut<SettingsFile>) {
+    if *settings == file.saved {
+        return;
+    }
+    match write_settings(&file.path, &settings) {
+        Ok(()) => {
+            file.saved = settings.clone();
+            file.modified = modified_time(&file.path);
+        }
+        Err(err) => error!(
+            "Unable to save the settings to {}: {err}",
+            file.path.display()
+        ),
+    }
+}
+
+fn text(value: &str) -> TextBundle {
+    TextBundle::from_section(
+        value,
+        TextStyle {
+            font_size: 18.,
+            ..default()
+        },
+    )
+}
+
+fn setting_button(parent: &mut ChildBuilder, label: &str, setting: Setting, step: i32) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    min_width: Val::Px(28.),
+                    padding: UiRect::horizontal(Val::Px(6.)),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: NORMAL_BUTTON.into(),
+                ..default()
+            },
+            SettingButton { setting, step },
+        ))
+        .with_children(|parent| {
+            let mut label = parent.spawn(text(label));
+            // Buttons without steps show the value
+            if step == 0 {
+                label.insert(SettingValue(setting));
+            }
+        });
+}
+
+fn setting_row(parent: &mut ChildBuilder, name: &str, setting: Setting) {
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                justify_content: JustifyContent::SpaceBetween,
+                align_items: AlignItems::Center,
+                column_gap: Val::Px(8.),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn(text(name));
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        align_items: AlignItems::Center,
+                        column_gap: Val::Px(4.),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| match setting {
+                    Setting::Key(_) | Setting::Units => setting_button(parent, "", setting, 0),
+                    _ => {
+                        setting_button(parent, "-", setting, -1);
+                        parent.spawn((text(""), SettingValue(setting)));
+                        setting_button(parent, "+", setting, 1);
+                    }
+                });
+        });
+}
+
+fn spawn_settings_panel(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(10.),
+                    top: Val::Px(10.),
+                    width: Val::Px(320.),
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(4.),
+                    padding: UiRect::all(Val::Px(10.)),
+                    ..default()
+                },
+                background_color: Color::rgba(0., 0., 0., 0.8).into(),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            SettingsPanel,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Settings",
+                TextStyle {
+                    font_size: 25.,
+                    ..default()
+                },
+            ));
+            setting_row(parent, "Mouse sensitivity", Setting::Sensitivity);
+            setting_row(parent, "Flying speed", Setting::Speed);
+            setting_row(parent, "Eye height", Setting::EyeHeight);
+            setting_row(parent, "Walking speed", Setting::WalkSpeed);
+            setting_row(parent, "Units", Setting::Units);
+            setting_row(parent, "Grid snap", Setting::Grid);
+            setting_row(parent, "Angle snap", Setting::SnapAngle);
+            setting_row(parent, "Wall snap distance", Setting::WallSnap);
+            setting_row(parent, "Frame limit", Setting::FrameLimit);
+            for binding in Binding::ALL {
+                setting_row(parent, binding.name(), Setting::Key(binding));
+            }
+        });
+}
+
+fn toggle_settings_panel(
+    keys: Res<Input<KeyCode>>,
+    mut panel: Query<&mut Visibility, With<SettingsPanel>>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    if !keys.just_pressed(KeyCode::F1) {
+        return;
+    }
+    let Ok(mut visibility) = panel.get_single_mut() else {
+        return;
+    };
+    *visibility = match *visibility {
+        Visibility::Hidden => Visibility::Inherited,
+        _ => Visibility::Hidden,
+    };
+    rebinding.0 = None;
+}
+
+// Moves a value by whole steps, keeping it at least one step
+fn stepped(value: f32, steps: f32, step: f32) -> f32 {
+    (value + steps * step).max(step)
+}
+
+// Moves a value to the next whole step, down to zero
+fn stepped_to_zero(value: f32, steps: f32, step: f32) -> f32 {
+    ((value / step).round() + steps).max(0.) * step
+}
+
+fn settings_buttons(
+    mut buttons: Query<(&Interaction, &mut BackgroundColor, &SettingButton), Changed<Interaction>>,
+    mut settings: ResMut<Settings>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    for (interaction, mut color, button) in &mut buttons {
+        match *interaction {
+            Interaction::Pressed => {
+                *color = PRESSED_BUTTON.into();
+                let step = button.step as f32;
+                match button.setting {
+                    Setting::Sensitivity => {
+                        settings.sensitivity =
+                            stepped(settings.sensitivity, step, SENSITIVITY_STEP);
+                    }
+                    Setting::Speed => settings.speed = stepped(settings.speed, step, SPEED_STEP),
+                    Setting::EyeHeight => {
+                        settings.eye_height = stepped(settings.eye_height, step, EYE_HEIGHT_STEP);
+                    }
+                    Setting::WalkSpeed => {
+                        settings.walk_speed = stepped(settings.walk_speed, step, WALK_SPEED_STEP);
+                    }
+                    Setting::Units => settings.units = settings.units.toggled(),
+                    Setting::Grid => {
+                        settings.grid = stepped_to_zero(settings.grid, step, GRID_STEP)
+                    }
+                    Setting::SnapAngle => {
+                        settings.snap_angle =
+                            stepped_to_zero(settings.snap_angle, step, SNAP_ANGLE_STEP);
+                    }
+                    Setting::WallSnap => {
+                        settings.wall_snap_distance =
+                            stepped_to_zero(settings.wall_snap_distance, step, WALL_SNAP_STEP);
+                    }
+                    Setting::FrameLimit => {
+                        settings.frame_limit = settings.frame_limit.step(button.step);
+                    }
+                    Setting::Key(binding) => rebinding.0 = Some(binding),
+                }
+            }
+            Interaction::Hovered => *color = HOVERED_BUTTON.into(),
+            Interaction::None => *color = NORMAL_BUTTON.into(),
+        }
+    }
+}
+
+// The next key pressed is bound, or Escape cancels. Keys the app uses
+// elsewhere are turned down, and every key press is used up while waiting.
+fn rebind_key(
+    mut keys: ResMut<Input<KeyCode>>,
+    mut rebinding: ResMut<Rebinding>,
+    mut settings: ResMut<Settings>,
+) {
+    let Some(binding) = rebinding.0 else {
+        return;
+    };
+    let pressed = keys.get_just_pressed().next().copied();
+    keys.reset_all();
+    let Some(key) = pressed else {
+        return;
+    };
+    if key == KeyCode::Escape {
+        rebinding.0 = None;
+        return;
+    }
+    if RESERVED_KEYS.contains(&key) {
+        warn!(
+            "{key:?} is used by a shortcut, press another key for {}",
+            binding.name()
+        );
+        return;
+    }
+    let mut bound = settings.keys;
+    let taken = Binding::ALL
+        .into_iter()
+        .find(|other| *other != binding && *other.key(&mut bound) == key);
+    if let Some(other) = taken {
+        warn!(
+            "{key:?} is already bound to {}, press another key for {}",
+            other.name(),
+            binding.name()
+        );
+        return;
+    }
+    rebinding.0 = None;
+    *binding.key(&mut settings.keys) = key;
+    info!("{} is now {key:?}", binding.name());
+}
+
+// Snapping steps of zero are shown as off
+fn snap_step(step: f32, shown: String) -> String {
+    if step > 0. {
+        shown
+    } else {
+        "Off".to_string()
+    }
+}
+
+fn show_settings(
+    settings: Res<Settings>,
+    rebinding: Res<Rebinding>,
+    mut values: Query<(&mut Text, &SettingValue)>,
+) {
+    if !settings.is_changed() && !rebinding.is_changed() {
+        return;
+    }
+    let mut keys = settings.keys;
+    for (mut text, value) in &mut values {
+        text.sections[0].value = match value.0 {
+            Setting::Sensitivity => format!("{:.5}", settings.sensitivity),
+            Setting::Speed => settings.units.format_speed(settings.speed),
+            Setting::EyeHeight => settings.units.format(settings.eye_height),
+            Setting::WalkSpeed => settings.units.format_speed(settings.walk_speed),
+            Setting::Units => format!("{:?}", settings.units),
+            Setting::Grid => snap_step(settings.grid, settings.units.format(settings.grid)),
+            Setting::SnapAngle => {
+                snap_step(settings.snap_angle, format!("{:.0}°", settings.snap_angle))
+            }
+            Setting::WallSnap => snap_step(
+                settings.wall_snap_distance,
+                settings.units.format(settings.wall_snap_distance),
+            ),
+            Setting::FrameLimit => settings.frame_limit.name(),
+            Setting::Key(binding) if rebinding.0 == Some(binding) => "Press a key".to_string(),
+            Setting::Key(binding) => format!("{:?}", *binding.key(&mut keys)),
+        };
+    }
+}
@@ -0,0 +1,85 @@
+use bevy::prelude::*;
+
+use crate::room::{closest_point_on_segment, Room};
+
+// Snapping applied while moving and rotating models. Holding Alt turns it off
+// for the current drag. A step of zero disables that kind of snapping. Set
+// from `Settings`.
+#[derive(Resource)]
+pub struct SnapSettings {
+    // Grid size for moving, in metres
+    pub grid: f32,
+    // Rotation increment, in radians
+    pub angle: f32,
+    // How close a model's back has to come to a wall to be pushed against it
+    pub wall_distance: f32,
+}
+
+impl Default for SnapSettings {
+    fn default() -> Self {
+        Self {
+            grid: 0.25,
+            angle: 15f32.to_radians(),
+            wall_distance: 0.3,
+        }
+    }
+}
+
+fn round_to(value: f32, step: f32) -> f32 {
+    if step > 0. {
+        (value / step).round() * step
+    } else {
+        value
+    }
+}
+
+impl SnapSettings {
+    pub fn is_active(keys: &Input<KeyCode>) -> bool {
+        !keys.any_pressed([KeyCode::AltLeft, KeyCode::AltRight])
+    }
+
+    pub fn position(&self, value: f32) -> f32 {
+        round_to(value, self.grid)
+    }
+
+    pub fn angle(&self, angle: f32) -> f32 {
+        round_to(angle, self.angle)
+    }
+
+    // Where a model with the given bounds has to be, and which way it has to
+    // face, for its back to sit flush against the closest wall. Models face
+    // +Z, so their back is the -Z side of their bounds.
+    pub fn wall(
+        &self,
+        room: &Room,
+        translation: Vec3,
+        bounds: (Vec3, Vec3),
+        scale: Vec3,
+    ) -> Option<(Vec3, Quat)> {
+        if self.wall_distance <= 0. {
+            return None;
+        }
+        let depth = -bounds.0.z * scale.z;
+        let position = translation.xz();
+        room.walls()
+            .filter_map(|(start, end)| {
+                let closest = closest_point_on_segment(position, start, end);
+                let normal = room.inward_normal(start, end);
+                let gap = (position - closest).dot(normal) - depth;
+                (gap.abs() <= self.wall_distance).then_some((gap.abs(), closest, normal))
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, closest, normal)| {
+                let flush = closest + normal * depth;
+                (
+                    Vec3::new(flush.x, translation.y, flush.y),
+                    Quat::from_rotation_y(normal.x.atan2(normal.y)),
+                )
+            })
+    }
+}
+
+// The rotation of a model around the vertical axis
+pub fn yaw(rotation: Quat) -> f32 {
+    rotation.to_euler(EulerRot::YXZ).0
+}
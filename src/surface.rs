@@ -0,0 +1,179 @@
+use bevy::{ecs::system::SystemParam, prelude::*, render::primitives::Aabb};
+use bevy_mod_picking::backends::raycast::bevy_mod_raycast::prelude::{
+    ray_intersection_over_mesh, Backfaces, Ray3d,
+};
+
+use crate::{
+    history::{EditCommand, History},
+    model_bounds,
+    pivot::PivotPending,
+    room::Room,
+    selection::Selection,
+    LoadingState, PlacedModel,
+};
+
+// Rests models on whatever is below them, the floor or the top of another
+// model. End drops the selected models onto the surface below them.
+pub struct SurfacePlugin;
+
+impl Plugin for SurfacePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                settle_new_models,
+                drop_selected.run_if(in_state(LoadingState::Loaded)),
+            ),
+        );
+    }
+}
+
+// A model that is rested on the surface below it once its scene has spawned
+// and its size is known
+#[derive(Component)]
+pub struct SettleOnSurface;
+
+#[derive(SystemParam)]
+pub struct Surfaces<'w, 's> {
+    room: Res<'w, Room>,
+    models: Query<'w, 's, Entity, With<PlacedModel>>,
+    children: Query<'w, 's, &'static Children>,
+    meshes: Query<
+        'w,
+        's,
+        (
+            &'static Handle<Mesh>,
+            &'static Aabb,
+            &'static GlobalTransform,
+        ),
+    >,
+    mesh_assets: Res<'w, Assets<Mesh>>,
+}
+
+impl Surfaces<'_, '_> {
+    // The closest point where a ray hits a placed model
+    pub fn model_hit(&self, ray: Ray3d, ignore: &[Entity]) -> Option<Vec3> {
+        let mut closest: Option<(f32, Vec3)> = None;
+        for model in self.models.iter().filter(|model| !ignore.contains(model)) {
+            for (mesh, aabb, transform) in
+                self.meshes.iter_many(self.children.iter_descendants(model))
+            {
+                let Some(mesh) = self.mesh_assets.get(mesh) else {
+                    continue;
+                };
+                let matrix = transform.compute_matrix();
+                if ray.intersects_aabb(aabb, &matrix).is_none() {
+                    continue;
+                }
+                // Scans often have inconsistent winding, so back faces count too
+                if let Some(hit) =
+                    ray_intersection_over_mesh(mesh, &matrix, &ray, Backfaces::Include)
+                {
+                    if closest.is_none_or(|(distance, _)| hit.distance() < distance) {
+                        closest = Some((hit.distance(), hit.position()));
+                    }
+                }
+            }
+        }
+        closest.map(|(_, position)| position)
+    }
+
+    // Height of the highest surface under any of the points, looking down
+    // from the ceiling. The floor is at zero.
+    fn highest(&self, points: &[Vec2], ignore: &[Entity]) -> f32 {
+        points
+            .iter()
+            .filter_map(|point| {
+                let ray = Ray3d::new(
+                    Vec3::new(point.x, self.room.wall_height, point.y),
+                    Vec3::NEG_Y,
+                );
+                self.model_hit(ray, ignore)
+            })
+            .fold(0., |highest, hit| highest.max(hit.y))
+    }
+
+    // The height that puts the bottom of a model with the given bounds on
+    // the highest surface under its footprint
+    pub fn resting_height(
+        &self,
+        transform: &Transform,
+        bounds: (Vec3, Vec3),
+        ignore: &[Entity],
+    ) -> f32 {
+        let (min, max) = bounds;
+        // Pulled in from the edges so a neighbour the model only touches
+        // doesn't hold it up
+        let inset = (max - min) * 0.1;
+        let (near, far) = (min + inset, max - inset);
+        let points = [
+            (near.x, near.z),
+            (far.x, near.z),
+            (near.x, far.z),
+            (far.x, far.z),
+            ((min.x + max.x) / 2., (min.z + max.z) / 2.),
+        ]
+        .map(|(x, z)| transform.transform_point(Vec3::new(x, min.y, z)).xz());
+        self.highest(&points, ignore) - min.y * transform.scale.y
+    }
+}
+
+fn settle_new_models(
+    mut commands: Commands,
+    mut models: Query<(Entity, &mut Transform, &GlobalTransform), With<SettleOnSurface>>,
+    children: Query<&Children>,
+    meshes: Query<(&Aabb, &GlobalTransform)>,
+    pending_pivots: Query<(), With<PivotPending>>,
+    surfaces: Surfaces,
+) {
+    for (entity, mut transform, global) in &mut models {
+        // The bounds change once the pivot is corrected
+        if children
+            .iter_descendants(entity)
+            .any(|child| pending_pivots.contains(child))
+        {
+            continue;
+        }
+        let Some(bounds) = model_bounds(entity, global, &children, &meshes) else {
+            continue;
+        };
+        transform.translation.y = surfaces.resting_height(&transform, bounds, &[entity]);
+        commands.entity(entity).remove::<SettleOnSurface>();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn drop_selected(
+    keys: Res<Input<KeyCode>>,
+    selection: Res<Selection>,
+    mut models: Query<(&mut Transform, &GlobalTransform), With<PlacedModel>>,
+    children: Query<&Children>,
+    meshes: Query<(&Aabb, &GlobalTransform)>,
+    surfaces: Surfaces,
+    mut history: ResMut<History>,
+) {
+    if !keys.just_pressed(KeyCode::End) {
+        return;
+    }
+    let mut dropped = Vec::new();
+    for &entity in selection.models() {
+        let Ok((mut transform, global)) = models.get_mut(entity) else {
+            continue;
+        };
+        let Some(bounds) = model_bounds(entity, global, &children, &meshes) else {
+            continue;
+        };
+        let before = *transform;
+        transform.translation.y = surfaces.resting_height(&transform, bounds, &[entity]);
+        if before != *transform {
+            dropped.push(EditCommand::Transform {
+                entity,
+                before,
+                after: *transform,
+            });
+        }
+    }
+    if !dropped.is_empty() {
+        history.push(EditCommand::Batch(dropped));
+    }
+}
@@ -0,0 +1,211 @@
+use bevy::{
+    input::mouse::MouseMotion,
+    prelude::*,
+    render::primitives::Aabb,
+    window::{CursorGrabMode, PrimaryWindow},
+};
+use bevy_flycam::{KeyBindings, MovementSettings};
+
+use crate::{
+    camera::CameraMode,
+    model_bounds,
+    room::{closest_point_on_segment, Room},
+    world_bounds, LoadingState, PlacedModel,
+};
+
+const GRAVITY: f32 = 9.81;
+// Pushing out of one obstacle can push into another, so it is repeated
+const COLLISION_PASSES: usize = 3;
+
+// Walks the camera around the room at eye height with the flycam's movement
+// keys and mouse look. Gravity keeps it on the floor, and the walls and
+// placed models stop it.
+pub struct WalkPlugin;
+
+impl Plugin for WalkPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WalkSettings>().add_systems(
+            Update,
+            walk.run_if(in_state(LoadingState::Loaded))
+                .run_if(resource_equals(CameraMode::Walk)),
+        );
+    }
+}
+
+// The eye height and speed are set from `Settings`
+#[derive(Resource)]
+pub struct WalkSettings {
+    pub eye_height: f32,
+    // Half the width of the walker, kept between the eyes and obstacles
+    pub radius: f32,
+    // Metres per second
+    pub speed: f32,
+    // Models no taller than this, like rugs, are walked over
+    pub step_height: f32,
+}
+
+impl Default for WalkSettings {
+    fn default() -> Self {
+        Self {
+            eye_height: 1.6,
+            radius: 0.25,
+            speed: 1.4,
+            step_height: 0.2,
+        }
+    }
+}
+
+// Moves `position` away from `closest` until they are `radius` apart
+fn push_out(position: Vec2, closest: Vec2, radius: f32) -> Vec2 {
+    let offset = position - closest;
+    let distance = offset.length();
+    if distance >= radius || distance == 0. {
+        return position;
+    }
+    closest + offset / distance * radius
+}
+
+// Moves the walker out of the walls and the footprints of placed models
+fn collide(mut position: Vec2, room: &Room, obstacles: &[(Vec2, Vec2)], radius: f32) -> Vec2 {
+    for _ in 0..COLLISION_PASSES {
+        for (start, end) in room.walls() {
+            let closest = closest_point_on_segment(position, start, end);
+            position = push_out(position, closest, radius);
+        }
+        for (min, max) in obstacles {
+            let closest = position.clamp(*min, *max);
+            if closest == position {
+                // Inside the footprint, so out through the nearest side
+                let exits = [
+                    (position.x - min.x, Vec2::new(min.x - radius, position.y)),
+                    (max.x - position.x, Vec2::new(max.x + radius, position.y)),
+                    (position.y - min.y, Vec2::new(position.x, min.y - radius)),
+                    (max.y - position.y, Vec2::new(position.x, max.y + radius)),
+                ];
+                if let Some((_, exit)) = exits.into_iter().min_by(|a, b| a.0.total_cmp(&b.0)) {
+                    position = exit;
+                }
+            } else {
+                position = push_out(position, closest, radius);
+            }
+        }
+    }
+    position
+}
+
+#[allow(clippy::too_many_arguments)]
+fn walk(
+    keys: Res<Input<KeyCode>>,
+    time: Res<Time>,
+    key_bindings: Res<KeyBindings>,
+    movement: Res<MovementSettings>,
+    settings: Res<WalkSettings>,
+    room: Res<Room>,
+    mut motion_events: EventReader<MouseMotion>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    models: Query<(Entity, &Transform, &GlobalTransform), With<PlacedModel>>,
+    children: Query<&Children>,
+    meshes: Query<(&Aabb, &GlobalTransform)>,
+    mut camera: Query<&mut Transform, (With<Camera3d>, Without<PlacedModel>)>,
+    mut fall_speed: Local<f32>,
+) {
+    let (Ok(window), Ok(mut transform)) = (window.get_single(), camera.get_single_mut()) else {
+        return;
+    };
+    let grabbed = window.cursor.grab_mode != CursorGrabMode::None;
+
+    // Looking around works the same as in the flycam
+    let (mut yaw, mut pitch, _) = transform.rotation.to_euler(EulerRot::YXZ);
+    for event in motion_events.read() {
+        if !grabbed {
+            continue;
+        }
+        let window_scale = window.height().min(window.width());
+        pitch -= (movement.sensitivity * event.delta.y * window_scale).to_radians();
+        yaw -= (movement.sensitivity * event.delta.x * window_scale).to_radians();
+    }
+    transform.rotation = Quat::from_axis_angle(Vec3::Y, yaw)
+        * Quat::from_axis_angle(Vec3::X, pitch.clamp(-1.54, 1.54));
+
+    // Standing still while Ctrl is held, as Ctrl shortcuts share keys with
+    // moving
+    let ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    let mut direction = Vec2::ZERO;
+    if grabbed && !ctrl {
+        let forward = Vec2::new(-yaw.sin(), -yaw.cos());
+        let right = Vec2::new(-forward.y, forward.x);
+        for (key, step) in [
+            (key_bindings.move_forward, forward),
+            (key_bindings.move_backward, -forward),
+            (key_bindings.move_left, -right),
+            (key_bindings.move_right, right),
+        ] {
+            if keys.pressed(key) {
+                direction += step;
+            }
+        }
+    }
+    let mut position = transform.translation.xz()
+        + direction.normalize_or_zero() * settings.speed * time.delta_seconds();
+    if !room.contains(position) {
+        position = transform.translation.xz();
+    }
+
+    // Falls until the eyes are at eye height above the floor
+    let eye_height = settings.eye_height.min(room.wall_height);
+    *fall_speed += GRAVITY * time.delta_seconds();
+    let mut height = transform.translation.y - *fall_speed * time.delta_seconds();
+    if height <= eye_height {
+        height = eye_height;
+        *fall_speed = 0.;
+    }
+
+    let feet = height - eye_height;
+    let obstacles: Vec<(Vec2, Vec2)> = models
+        .iter()
+        .filter_map(|(entity, model, global)| {
+            let bounds = model_bounds(entity, global, &children, &meshes)?;
+            let (min, max) = world_bounds(bounds, model);
+            let blocks = max.y > feet + settings.step_height && min.y < height;
+            blocks.then_some((min.xz(), max.xz()))
+        })
+        .collect();
+    let position = collide(position, &room, &obstacles, settings.radius);
+    transform.translation = Vec3::new(position.x, height, position.y);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RADIUS: f32 = 0.25;
+
+    #[test]
+    fn leaves_free_positions_alone() {
+        let room = Room::rectangle(4., 4., 2.5);
+        assert_eq!(collide(Vec2::ZERO, &room, &[], RADIUS), Vec2::ZERO);
+    }
+
+    #[test]
+    fn keeps_away_from_walls() {
+        let room = Room::rectangle(4., 4., 2.5);
+        let position = collide(Vec2::new(1.9, 0.), &room, &[], RADIUS);
+        assert!((position.x - (2. - RADIUS)).abs() < 1e-5);
+        assert_eq!(position.y, 0.);
+        // Pushed out of both walls in a corner
+        let position = collide(Vec2::new(-1.9, 1.9), &room, &[], RADIUS);
+        assert!(position.abs_diff_eq(Vec2::new(-1.75, 1.75), 1e-5));
+    }
+
+    #[test]
+    fn steps_out_of_model_footprints() {
+        let room = Room::rectangle(10., 10., 2.5);
+        let table = (Vec2::new(-1., -0.5), Vec2::new(1., 0.5));
+        // Inside, out through the nearest side
+        let position = collide(Vec2::new(0.2, 0.4), &room, &[table], RADIUS);
+        assert!(position.abs_diff_eq(Vec2::new(0.2, 0.5 + RADIUS), 1e-5));
+        // Outside but too close
+        let position = collide(Vec2::new(1.1, 0.), &room, &[table], RADIUS);
+        assert!(position.abs_diff_eq(Vec2::new(1. + RADIUS, 0.), 1e-5));
+    }
+}